@@ -20,6 +20,18 @@ impl GenerationalId {
 
 }
 
+///Defines what happens to an Entity slot once its generation has reached u32::MAX and it is freed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum GenerationPolicy {
+    ///The slot is never reused, so no stale GenerationalId can ever refer to a new Entity.
+    Retire,
+    ///The generation wraps back around to 1, so very old GenerationalIds may become valid again.
+    Wrap,
+    ///Panics in debug builds to surface the hot slot, and retires the slot in release builds.
+    Panic
+}
+
 impl Hash for GenerationalId {
     fn hash<H: Hasher>(&self, state: &mut H) {
         let complete: u64 = ((self.id as u64) << 32) | ((self.gen as u64));
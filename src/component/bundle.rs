@@ -0,0 +1,56 @@
+use crate::component::component::*;
+use crate::common::generational_id::*;
+use crate::world::world::*;
+
+use std::any::*;
+use std::vec::Vec;
+
+///A group of Components that are attached to an Entity together.
+///
+///Bundle is implemented for tuples of up to eight Components, and is used by World::spawn_with
+///to build an Entity in a single step that either fully succeeds or leaves nothing behind.
+///
+pub trait Bundle: Send + Sync + 'static {
+    ///Returns the TypeId and type name of every Component in the Bundle, in order.
+    fn component_types() -> Vec<(TypeId, &'static str)>;
+
+    ///Returns every Component in the Bundle as a trait object, in order.
+    fn components(&self) -> Vec<&dyn Component>;
+
+    ///Attaches every Component in the Bundle to the Entity, in order, stopping at the first failure.
+    ///Components stored in Archetypes are attached last, moving the Entity only once.
+    fn attach_all(self, world: &World, handle: GenerationalId) -> Result<(), String>;
+}
+
+macro_rules! impl_bundle {
+    ($($name:ident),+) => {
+        impl<$($name: Component),+> Bundle for ($($name,)+) {
+            fn component_types() -> Vec<(TypeId, &'static str)> {
+                vec![$((TypeId::of::<$name>(), type_name::<$name>())),+]
+            }
+
+            #[allow(non_snake_case)]
+            fn components(&self) -> Vec<&dyn Component> {
+                let ($($name,)+) = self;
+                vec![$($name as &dyn Component),+]
+            }
+
+            #[allow(non_snake_case)]
+            fn attach_all(self, world: &World, handle: GenerationalId) -> Result<(), String> {
+                let ($($name,)+) = self;
+                let mut staged = Vec::new();
+                $(world.attach_or_stage(handle, $name, &mut staged)?;)+
+                world.attach_staged(handle, staged)
+            }
+        }
+    }
+}
+
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
@@ -1,7 +1,9 @@
-use std::slice::{Iter, IterMut};
 use std::option::*;
+use std::vec::Vec;
+use std::any::type_name;
 use crate::component::component::*;
 use crate::common::generational_id::*;
+use crate::component::tracked_manager::*;
 
 #[macro_use]
 use downcast_rs::*;
@@ -20,6 +22,14 @@ pub trait GeneralComponentManager: DowncastSync {
     ///Fetches a given Component of an Entity as a mutable trait object.
     fn fetch_dyn_mut(&mut self, owner: GenerationalId) -> Option<Box<&mut dyn Component>>;
 
+    ///Inserts a Component given as a trait object, attached to owner. Fails if the concrete type of
+    ///the Component is not the type this ComponentManager stores.
+    fn general_insert(&mut self, owner: GenerationalId, value: Box<dyn Component>) -> Result<(), String>;
+
+    ///Checks whether every Component given as a trait object could be inserted for Entities that
+    ///have none in this ComponentManager yet, without inserting anything.
+    fn general_check_insert(&self, values: &[&dyn Component]) -> Result<(), String>;
+
     ///Checks to see if the provided Entity is the owner of a Component in this ComponentManager.
     fn general_has_component(&self, owner: GenerationalId) -> bool;
 
@@ -32,9 +42,23 @@ pub trait GeneralComponentManager: DowncastSync {
     ///Updates the storage (if insertion or deletion has been deferred) and any non-Component
     ///internal variables, such as statistics or other metadata.
     fn general_update(&mut self);
+
+    ///Carries out any deferred deletions without otherwise starting a new frame, so records kept
+    ///between two updates, such as the owners of removed Components, are left alone.
+    fn general_flush(&mut self);
+
+    ///Moves the Component of each old owner over to its new owner, in order. Every Component is
+    ///taken out before any is reinserted, so owners may be swapped or shifted freely.
+    fn general_remap(&mut self, remap: &[(GenerationalId, GenerationalId)]);
 }
 impl_downcast!(sync GeneralComponentManager);
 
+///An iterator over every Component in a ComponentManager, immutably.
+pub type ComponentIter<'a, T> = Box<dyn Iterator<Item = &'a T> + 'a>;
+
+///An iterator over every Component in a ComponentManager, mutably.
+pub type ComponentIterMut<'a, T> = Box<dyn Iterator<Item = &'a mut T> + 'a>;
+
 ///Methods for adding Components to, deleting Components from, iterating over, querying, and updating
 ///ComponentManagers.
 ///
@@ -48,45 +72,108 @@ pub trait ComponentManager: DowncastSync + GeneralComponentManager + std::fmt::D
     type Data: Component;
 
     ///Returns an iterator that iterates over every Component in storage immutably (i.e. read only).
-    fn iter(&self) -> Iter<Self::Data>;
+    fn iter(&self) -> ComponentIter<'_, Self::Data>;
 
     ///Returns an iterator that iterates over every Component in storage mutably (i.e. read-write).
-    fn iter_mut(&mut self) -> IterMut<Self::Data>;
+    fn iter_mut(&mut self) -> ComponentIterMut<'_, Self::Data>;
+
+    ///Returns every Component in storage as one contiguous slice, if the storage is dense. None by
+    ///default.
+    #[allow(dead_code)]
+    fn as_slice(&self) -> Option<&[Self::Data]> {
+        None
+    }
+
+    ///Returns every Component in storage as one contiguous mutable slice, if the storage is dense.
+    ///None by default.
+    #[allow(dead_code)]
+    fn as_mut_slice(&mut self) -> Option<&mut [Self::Data]> {
+        None
+    }
 
     ///Immutably fetches a single Component attached to the Entity represented by owner, if it exists.
     fn fetch(&self, owner: GenerationalId) -> Option<&Self::Data>;
 
     ///Mutably fetches a single Component attached to the Entity represented by owner, if it exists.
+    #[allow(dead_code)]
     fn fetch_mut(&mut self, owner: GenerationalId) -> Option<&mut Self::Data>;
 
+    ///Immutably fetches the Component of owner if its deletion has been deferred and fetch no longer
+    ///returns it, so it can still be looked at until update drops it. None by default, for storages
+    ///whose fetch keeps returning such Components until then.
+    fn fetch_deleting(&self, _owner: GenerationalId) -> Option<&Self::Data> {
+        None
+    }
+
     ///Returns whether or not the given Entity has a Component in this Manager.
     fn has_component(&self, owner: GenerationalId) -> bool;
 
     ///Inserts a Component attached to owner into storage (allowed to be deferred if needed).
     fn insert(&mut self, owner: GenerationalId, value: Self::Data) -> Result<(), String>;
 
+    ///Checks whether every Component in values could be inserted for Entities that have none in
+    ///storage yet, without inserting anything. Only storages that constrain the values themselves,
+    ///such as a unique NameComponentManager, need to override it.
+    fn check_insert(&self, _values: &[&Self::Data]) -> Result<(), String> {
+        Ok(())
+    }
+
     ///Deletes a Component from storage (may be deferred, but should make that Component inaccessible).
     fn delete(&mut self, owner: GenerationalId) -> Result<(), String>;
 
     ///Deletes a Component from storage immediately. Not allowed to defer deletion.
     fn delete_now(&mut self, owner: GenerationalId) -> Result<(), String>;
 
+    ///Removes a Component from storage immediately and returns it. Not allowed to defer removal.
+    fn take(&mut self, owner: GenerationalId) -> Result<Self::Data, String>;
+
     ///Executes any deferred operations and updates non-Component storage variables, if any.
     fn update(&mut self);
+
+    ///Executes any deferred deletions without otherwise starting a new frame. The same as update
+    ///by default, which only storages keeping per-frame records need to change.
+    fn flush(&mut self) {
+        self.update();
+    }
+
+    ///Moves the Component of each old owner over to its new owner, in order. Every Component is
+    ///taken out before any is reinserted, so owners may be swapped or shifted freely.
+    fn remap(&mut self, remap: &[(GenerationalId, GenerationalId)]) {
+        let mut moved = Vec::new();
+
+        for (old, new) in remap.iter() {
+            if let Ok(c) = self.take(*old) {
+                moved.push((*new, c));
+            }
+        }
+
+        for (new, mut c) in moved {
+            c.set_owner(new);
+            let _ = self.insert(new, c);
+        }
+    }
 }
 impl_downcast!(sync ComponentManager assoc Data where Data: Component);
 
 impl<C: Component> ComponentManager for Box<dyn ComponentManager<Data=C>> {
     type Data = C;
 
-    fn iter(&self) -> Iter<Self::Data> {
+    fn iter(&self) -> ComponentIter<'_, Self::Data> {
         <dyn ComponentManager<Data=C>>::iter(&**self)
     }
 
-    fn iter_mut(&mut self) -> IterMut<Self::Data> {
+    fn iter_mut(&mut self) -> ComponentIterMut<'_, Self::Data> {
         <dyn ComponentManager<Data=C>>::iter_mut(&mut **self)
     }
 
+    fn as_slice(&self) -> Option<&[Self::Data]> {
+        <dyn ComponentManager<Data=C>>::as_slice(&**self)
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [Self::Data]> {
+        <dyn ComponentManager<Data=C>>::as_mut_slice(&mut **self)
+    }
+
     fn fetch(&self, owner: GenerationalId) -> Option<&Self::Data> {
         <dyn ComponentManager<Data=C>>::fetch(&**self, owner)
     }
@@ -95,6 +182,10 @@ impl<C: Component> ComponentManager for Box<dyn ComponentManager<Data=C>> {
         <dyn ComponentManager<Data=C>>::fetch_mut(&mut **self, owner)
     }
 
+    fn fetch_deleting(&self, owner: GenerationalId) -> Option<&Self::Data> {
+        <dyn ComponentManager<Data=C>>::fetch_deleting(&**self, owner)
+    }
+
     fn has_component(&self, owner: GenerationalId) -> bool {
         <dyn ComponentManager<Data=C>>::has_component(&**self, owner)
     }
@@ -103,6 +194,10 @@ impl<C: Component> ComponentManager for Box<dyn ComponentManager<Data=C>> {
         <dyn ComponentManager<Data=C>>::insert(&mut **self, owner, value)
     }
 
+    fn check_insert(&self, values: &[&Self::Data]) -> Result<(), String> {
+        <dyn ComponentManager<Data=C>>::check_insert(&**self, values)
+    }
+
     fn delete(&mut self, owner: GenerationalId) -> Result<(), String> {
         <dyn ComponentManager<Data=C>>::delete(&mut **self, owner)
     }
@@ -111,9 +206,21 @@ impl<C: Component> ComponentManager for Box<dyn ComponentManager<Data=C>> {
         <dyn ComponentManager<Data=C>>::delete_now(&mut **self, owner)
     }
 
+    fn take(&mut self, owner: GenerationalId) -> Result<Self::Data, String> {
+        <dyn ComponentManager<Data=C>>::take(&mut **self, owner)
+    }
+
     fn update(&mut self) {
         <dyn ComponentManager<Data=C>>::update(&mut **self);
     }
+
+    fn flush(&mut self) {
+        <dyn ComponentManager<Data=C>>::flush(&mut **self);
+    }
+
+    fn remap(&mut self, remap: &[(GenerationalId, GenerationalId)]) {
+        <dyn ComponentManager<Data=C>>::remap(&mut **self, remap);
+    }
 }
 
 impl<CM: ComponentManager> GeneralComponentManager for CM {
@@ -131,6 +238,29 @@ impl<CM: ComponentManager> GeneralComponentManager for CM {
         }
     }
 
+    fn general_insert(&mut self, owner: GenerationalId, value: Box<dyn Component>) -> Result<(), String> {
+        match value.downcast::<CM::Data>() {
+            Ok(c) => {
+                let mut c = *c;
+                c.set_owner(owner);
+                self.insert(owner, c)
+            },
+            Err(v) => Err(format!("Cannot insert a {} into a ComponentManager for {}", v.type_name(), type_name::<CM::Data>()))
+        }
+    }
+
+    fn general_check_insert(&self, values: &[&dyn Component]) -> Result<(), String> {
+        let mut typed = Vec::with_capacity(values.len());
+        for v in values.iter() {
+            match (*v).downcast_ref::<CM::Data>() {
+                Some(c) => typed.push(c),
+                None => return Err(format!("Cannot insert a {} into a ComponentManager for {}", v.type_name(), type_name::<CM::Data>()))
+            }
+        }
+
+        self.check_insert(&typed)
+    }
+
     fn general_has_component(&self, owner: GenerationalId) -> bool {
         self.has_component(owner)
     }
@@ -146,6 +276,14 @@ impl<CM: ComponentManager> GeneralComponentManager for CM {
     fn general_update(&mut self) {
         self.update()
     }
+
+    fn general_flush(&mut self) {
+        self.flush()
+    }
+
+    fn general_remap(&mut self, remap: &[(GenerationalId, GenerationalId)]) {
+        self.remap(remap)
+    }
 }
 
 pub fn downcast_read_lock<'a, T: Component>(guard: &'a std::sync::RwLockReadGuard<Box<dyn GeneralComponentManager>>) -> &'a Box<dyn ComponentManager<Data=T>> {
@@ -155,3 +293,11 @@ pub fn downcast_read_lock<'a, T: Component>(guard: &'a std::sync::RwLockReadGuar
 pub fn downcast_write_lock<'a, T: Component>(guard: &'a mut std::sync::RwLockWriteGuard<Box<dyn GeneralComponentManager>>) -> &'a mut Box<dyn ComponentManager<Data=T>> {
     (*guard).downcast_mut::<Box<dyn ComponentManager<Data = T>>>().unwrap()
 }
+
+pub fn tracked_read_lock<'a, T: Component>(guard: &'a std::sync::RwLockReadGuard<Box<dyn GeneralComponentManager>>) -> &'a TrackedManager<T> {
+    downcast_read_lock::<T>(guard).downcast_ref::<TrackedManager<T>>().unwrap()
+}
+
+pub fn tracked_write_lock<'a, T: Component>(guard: &'a mut std::sync::RwLockWriteGuard<Box<dyn GeneralComponentManager>>) -> &'a mut TrackedManager<T> {
+    downcast_write_lock::<T>(guard).downcast_mut::<TrackedManager<T>>().unwrap()
+}
@@ -0,0 +1,188 @@
+use crate::component::component::*;
+use crate::component::component_manager::*;
+use crate::component::pending_drops::*;
+use crate::common::generational_id::*;
+
+use std::collections::BTreeMap;
+
+///A ComponentManager storing Components in a BTreeMap keyed by their owner.
+///
+///Meant for Components that only a handful of Entities ever have, such as a PlayerController,
+///where a dense Vec with an index map, or the pages of a SparseSetStorage, would mostly sit empty.
+///Memory use is proportional to the number of Components alone, and iter walks them in order of
+///their owners' ids. There is no contiguous slice, so as_slice returns None. A deferred delete moves
+///the Component aside right away, where it can no longer be fetched or iterated, and only drops it
+///on the next update.
+///
+#[derive(Debug)]
+pub struct MapStorage<T: Component> {
+    components: BTreeMap<GenerationalId, T>,
+    pending_at: BTreeMap<GenerationalId, usize>,
+    pending: PendingDrops<T>
+}
+
+#[allow(dead_code)]
+impl<T: Component> MapStorage<T> {
+
+    ///Creates a new, empty MapStorage.
+    pub fn new() -> MapStorage<T> {
+        MapStorage {
+            components: BTreeMap::new(),
+            pending_at: BTreeMap::new(),
+            pending: PendingDrops::new()
+        }
+    }
+
+    ///Returns the number of Components stored.
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    ///Returns whether or not no Components are stored.
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    //Takes the Component at index ind of the pending drops back out.
+    fn take_pending(&mut self, ind: usize) -> T {
+        let owner = self.pending.owner(ind);
+        let (comp, moved) = self.pending.take(ind);
+        self.pending_at.remove(&owner);
+
+        if let Some(moved) = moved {
+            self.pending_at.insert(moved, ind);
+        }
+
+        comp
+    }
+}
+
+impl<T: Component + std::fmt::Debug> ComponentManager for MapStorage<T> {
+    type Data = T;
+
+    fn iter(&self) -> ComponentIter<'_, T> {
+        Box::new(self.components.values())
+    }
+
+    fn iter_mut(&mut self) -> ComponentIterMut<'_, T> {
+        Box::new(self.components.values_mut())
+    }
+
+    fn fetch(&self, owner: GenerationalId) -> Option<&T> {
+        self.components.get(&owner)
+    }
+
+    fn fetch_mut(&mut self, owner: GenerationalId) -> Option<&mut T> {
+        self.components.get_mut(&owner)
+    }
+
+    fn fetch_deleting(&self, owner: GenerationalId) -> Option<&T> {
+        self.pending_at.get(&owner).map(|i| self.pending.get(*i))
+    }
+
+    fn has_component(&self, owner: GenerationalId) -> bool {
+        self.components.contains_key(&owner)
+    }
+
+    fn insert(&mut self, owner: GenerationalId, value: T) -> Result<(), String> {
+        if self.components.contains_key(&owner) {
+            return Err(format!("Cannot attach multiple of the same component to Entity {}", owner.id));
+        }
+
+        if let Some(i) = self.pending_at.get(&owner) {
+            let i = *i;
+            self.take_pending(i);
+        }
+
+        self.components.insert(owner, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, owner: GenerationalId) -> Result<(), String> {
+        match self.components.remove(&owner) {
+            Some(c) => {
+                let i = self.pending.hold(owner, c);
+                self.pending_at.insert(owner, i);
+                Ok(())
+            },
+            None if self.pending_at.contains_key(&owner) => Ok(()),
+            None => Err(format!("Entity {} does not have a {}", owner.id, std::any::type_name::<T>()))
+        }
+    }
+
+    fn delete_now(&mut self, owner: GenerationalId) -> Result<(), String> {
+        self.take(owner).map(|_| ())
+    }
+
+    fn take(&mut self, owner: GenerationalId) -> Result<T, String> {
+        if let Some(c) = self.components.remove(&owner) {
+            return Ok(c);
+        }
+
+        match self.pending_at.get(&owner) {
+            Some(i) => {
+                let i = *i;
+                Ok(self.take_pending(i))
+            },
+            None => Err(format!("Entity {} does not have a {}", owner.id, std::any::type_name::<T>()))
+        }
+    }
+
+    fn update(&mut self) {
+        self.pending.clear();
+        self.pending_at.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::test_components::*;
+
+    fn insert(s: &mut MapStorage<Position>, owner: GenerationalId, x: i32) {
+        s.insert(owner, Position::new(owner, x)).unwrap();
+    }
+
+    #[test]
+    fn iterates_in_owner_order_without_a_slice() {
+        let mut s = MapStorage::new();
+        insert(&mut s, id(900, 1), 3);
+        insert(&mut s, id(2, 1), 1);
+        insert(&mut s, id(40, 1), 2);
+
+        assert_eq!(s.iter().map(|p| p.x).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(s.as_slice().is_none());
+        assert!(s.as_mut_slice().is_none());
+        assert_eq!(s.len(), 3);
+    }
+
+    #[test]
+    fn generations_of_one_slot_are_separate_owners() {
+        let mut s = MapStorage::new();
+        insert(&mut s, id(4, 1), 1);
+        insert(&mut s, id(4, 2), 2);
+
+        assert_eq!(s.fetch(id(4, 1)).unwrap().x, 1);
+        assert_eq!(s.fetch(id(4, 2)).unwrap().x, 2);
+        assert!(s.insert(id(4, 2), Position::new(id(4, 2), 0)).is_err());
+    }
+
+    #[test]
+    fn take_cancels_pending_delete() {
+        let mut s = MapStorage::new();
+        insert(&mut s, id(0, 1), 1);
+        insert(&mut s, id(1, 1), 2);
+
+        s.delete(id(0, 1)).unwrap();
+        s.delete(id(1, 1)).unwrap();
+        assert!(!s.has_component(id(0, 1)));
+
+        s.take(id(0, 1)).unwrap();
+        insert(&mut s, id(0, 1), 5);
+        s.update();
+
+        assert_eq!(s.fetch(id(0, 1)).unwrap().x, 5);
+        assert!(!s.has_component(id(1, 1)));
+        assert!(s.delete(id(1, 1)).is_err());
+    }
+}
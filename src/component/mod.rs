@@ -1,2 +1,11 @@
 pub mod component;
 pub mod component_manager;
+pub mod bundle;
+pub mod tracked_manager;
+pub mod vec_storage;
+pub mod sparse_set_storage;
+pub mod tag_storage;
+pub mod map_storage;
+pub mod pending_drops;
+#[cfg(test)]
+pub mod test_components;
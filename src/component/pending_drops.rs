@@ -0,0 +1,79 @@
+use crate::common::generational_id::*;
+
+use std::vec::Vec;
+
+///Where a storage keeps the Component of an owner: among its live Components, or held in its
+///PendingDrops after a deferred delete. Either way, the index is into that collection.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Held {
+    Live(usize),
+    Pending(usize)
+}
+
+///Components taken out of a storage by a deferred delete, held until the next update drops them.
+///
+///A deferred delete has to make a Component inaccessible right away, while on_remove hooks still
+///need to see it until it is dropped. Storages move such Components in here, and record the index
+///returned by hold as Held::Pending wherever they look up owners, so finding one again costs no
+///more than finding a live Component. Inserting for an owner whose Component is still held takes
+///the held one back out first, so it is dropped in favor of the new one.
+///
+#[derive(Debug)]
+pub struct PendingDrops<T> {
+    comps: Vec<T>,
+    owners: Vec<GenerationalId>
+}
+
+#[allow(dead_code)]
+impl<T> PendingDrops<T> {
+
+    ///Creates a new, empty PendingDrops.
+    pub fn new() -> PendingDrops<T> {
+        PendingDrops {
+            comps: Vec::new(),
+            owners: Vec::new()
+        }
+    }
+
+    ///Returns the number of held Components.
+    pub fn len(&self) -> usize {
+        self.comps.len()
+    }
+
+    ///Returns whether or not no Components are held.
+    pub fn is_empty(&self) -> bool {
+        self.comps.is_empty()
+    }
+
+    ///Holds a Component deleted from owner, and returns its index.
+    pub fn hold(&mut self, owner: GenerationalId, comp: T) -> usize {
+        self.comps.push(comp);
+        self.owners.push(owner);
+        self.comps.len() - 1
+    }
+
+    ///Returns the held Component at index i.
+    pub fn get(&self, i: usize) -> &T {
+        &self.comps[i]
+    }
+
+    ///Returns the owner of the held Component at index i.
+    pub fn owner(&self, i: usize) -> GenerationalId {
+        self.owners[i]
+    }
+
+    ///Takes the held Component at index i back out. The last held Component is moved into index i
+    ///in its place, and its owner is returned alongside, so the storage can point it at i.
+    pub fn take(&mut self, i: usize) -> (T, Option<GenerationalId>) {
+        let comp = self.comps.swap_remove(i);
+        self.owners.swap_remove(i);
+
+        (comp, self.owners.get(i).copied())
+    }
+
+    ///Drops every held Component, and returns the owners they were deleted from.
+    pub fn clear(&mut self) -> Vec<GenerationalId> {
+        self.comps.clear();
+        std::mem::take(&mut self.owners)
+    }
+}
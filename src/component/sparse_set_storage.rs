@@ -0,0 +1,334 @@
+use crate::component::component::*;
+use crate::component::component_manager::*;
+use crate::component::pending_drops::*;
+use crate::common::generational_id::*;
+
+use std::vec::Vec;
+
+///The number of Entity slots covered by each page of the sparse array.
+pub const SPARSE_PAGE_SIZE: usize = 256;
+
+//Marks a slot in a sparse page that has no Component.
+const EMPTY: u32 = u32::MAX;
+
+//Set on a slot in a sparse page whose index is into the pending drops rather than the dense Vec.
+const PENDING: u32 = 1 << 31;
+
+///A ComponentManager storing Components densely, found through a sparse array indexed by Entity
+///slot.
+///
+///The sparse array maps GenerationalId.id to an index into the dense Vec of Components, so
+///fetch and has_component are an array lookup followed by a generation check rather than a hash.
+///It is split into pages of SPARSE_PAGE_SIZE slots, which are only allocated once a Component is
+///inserted for one of their slots, so a few Components on high ids do not cost a full array.
+///Each slot holds at most one Component, so inserting for one generation fails while the Component
+///of another generation still occupies the slot.
+///As with VecStorage, removal swaps the last Component into the freed index, and iter, iter_mut
+///and as_slice walk the dense Vec. A deferred delete takes the Component out of the dense Vec
+///right away and only drops it on the next update; until then its slot points at it among the
+///pending drops, and inserting for any generation of the slot drops it early.
+///
+#[derive(Debug)]
+pub struct SparseSetStorage<T: Component> {
+    sparse: Vec<Option<Box<[u32; SPARSE_PAGE_SIZE]>>>,
+    components: Vec<T>,
+    owners: Vec<GenerationalId>,
+    pending: PendingDrops<T>
+}
+
+#[allow(dead_code)]
+impl<T: Component> SparseSetStorage<T> {
+
+    ///Creates a new, empty SparseSetStorage.
+    pub fn new() -> SparseSetStorage<T> {
+        SparseSetStorage {
+            sparse: Vec::new(),
+            components: Vec::new(),
+            owners: Vec::new(),
+            pending: PendingDrops::new()
+        }
+    }
+
+    ///Returns the number of Components stored.
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    ///Returns whether or not no Components are stored.
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    ///Returns the owners of the stored Components, in the same order as iter.
+    pub fn owners(&self) -> &[GenerationalId] {
+        &self.owners
+    }
+
+    //Returns where the sparse array says the Component for the slot of id is, regardless of
+    //generation.
+    fn slot(&self, id: u32) -> Option<Held> {
+        let id = id as usize;
+        match self.sparse.get(id / SPARSE_PAGE_SIZE) {
+            Some(Some(page)) => match page[id % SPARSE_PAGE_SIZE] {
+                EMPTY => None,
+                i if i & PENDING != 0 => Some(Held::Pending((i & !PENDING) as usize)),
+                i => Some(Held::Live(i as usize))
+            },
+            _ => None
+        }
+    }
+
+    //Returns the owner of the Component at held.
+    fn owner_at(&self, held: Held) -> GenerationalId {
+        match held {
+            Held::Live(i) => self.owners[i],
+            Held::Pending(i) => self.pending.owner(i)
+        }
+    }
+
+    //Returns where the Component owned by owner is, checking the generation.
+    fn index_of(&self, owner: GenerationalId) -> Option<Held> {
+        self.slot(owner.id).filter(|held| self.owner_at(*held) == owner)
+    }
+
+    fn set_slot(&mut self, id: u32, held: Option<Held>) {
+        let id = id as usize;
+        let page = id / SPARSE_PAGE_SIZE;
+
+        if self.sparse.len() <= page {
+            self.sparse.resize_with(page + 1, || None);
+        }
+
+        let value = match held {
+            Some(Held::Live(i)) => i as u32,
+            Some(Held::Pending(i)) => i as u32 | PENDING,
+            None => EMPTY
+        };
+        self.sparse[page].get_or_insert_with(|| Box::new([EMPTY; SPARSE_PAGE_SIZE]))[id % SPARSE_PAGE_SIZE] = value;
+    }
+
+    //Removes the Component at index ind by swapping the last Component into its place.
+    fn remove_at(&mut self, ind: usize) -> T {
+        let comp = self.components.swap_remove(ind);
+        let owner = self.owners.swap_remove(ind);
+        self.set_slot(owner.id, None);
+
+        if ind < self.owners.len() {
+            self.set_slot(self.owners[ind].id, Some(Held::Live(ind)));
+        }
+
+        comp
+    }
+
+    //Takes the Component at index ind of the pending drops back out.
+    fn take_pending(&mut self, ind: usize) -> T {
+        let owner = self.pending.owner(ind);
+        let (comp, moved) = self.pending.take(ind);
+        self.set_slot(owner.id, None);
+
+        if let Some(moved) = moved {
+            self.set_slot(moved.id, Some(Held::Pending(ind)));
+        }
+
+        comp
+    }
+}
+
+impl<T: Component + std::fmt::Debug> ComponentManager for SparseSetStorage<T> {
+    type Data = T;
+
+    fn iter(&self) -> ComponentIter<'_, T> {
+        Box::new(self.components.iter())
+    }
+
+    fn iter_mut(&mut self) -> ComponentIterMut<'_, T> {
+        Box::new(self.components.iter_mut())
+    }
+
+    fn as_slice(&self) -> Option<&[T]> {
+        Some(&self.components)
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [T]> {
+        Some(&mut self.components)
+    }
+
+    fn fetch(&self, owner: GenerationalId) -> Option<&T> {
+        match self.index_of(owner) {
+            Some(Held::Live(i)) => Some(&self.components[i]),
+            _ => None
+        }
+    }
+
+    fn fetch_mut(&mut self, owner: GenerationalId) -> Option<&mut T> {
+        match self.index_of(owner) {
+            Some(Held::Live(i)) => Some(&mut self.components[i]),
+            _ => None
+        }
+    }
+
+    fn fetch_deleting(&self, owner: GenerationalId) -> Option<&T> {
+        match self.index_of(owner) {
+            Some(Held::Pending(i)) => Some(self.pending.get(i)),
+            _ => None
+        }
+    }
+
+    fn has_component(&self, owner: GenerationalId) -> bool {
+        matches!(self.index_of(owner), Some(Held::Live(_)))
+    }
+
+    fn insert(&mut self, owner: GenerationalId, value: T) -> Result<(), String> {
+        //Each slot holds a single Component, so one left by another generation must be removed
+        //through its own owner first.
+        match self.slot(owner.id) {
+            Some(held @ Held::Live(_)) if self.owner_at(held) == owner => {
+                return Err(format!("Cannot attach multiple of the same component to Entity {}", owner.id));
+            },
+            Some(Held::Live(i)) => {
+                return Err(format!("Entity slot {} still holds a {} of generation {}", owner.id, std::any::type_name::<T>(), self.owners[i].gen));
+            },
+            Some(Held::Pending(i)) => {
+                self.take_pending(i);
+            },
+            None => {}
+        }
+
+        self.set_slot(owner.id, Some(Held::Live(self.components.len())));
+        self.components.push(value);
+        self.owners.push(owner);
+
+        Ok(())
+    }
+
+    fn delete(&mut self, owner: GenerationalId) -> Result<(), String> {
+        match self.index_of(owner) {
+            Some(Held::Live(i)) => {
+                let comp = self.remove_at(i);
+                let i = self.pending.hold(owner, comp);
+                self.set_slot(owner.id, Some(Held::Pending(i)));
+                Ok(())
+            },
+            Some(Held::Pending(_)) => Ok(()),
+            None => Err(format!("Entity {} does not have a {}", owner.id, std::any::type_name::<T>()))
+        }
+    }
+
+    fn delete_now(&mut self, owner: GenerationalId) -> Result<(), String> {
+        self.take(owner).map(|_| ())
+    }
+
+    fn take(&mut self, owner: GenerationalId) -> Result<T, String> {
+        match self.index_of(owner) {
+            Some(Held::Live(i)) => Ok(self.remove_at(i)),
+            Some(Held::Pending(i)) => Ok(self.take_pending(i)),
+            None => Err(format!("Entity {} does not have a {}", owner.id, std::any::type_name::<T>()))
+        }
+    }
+
+    fn update(&mut self) {
+        for owner in self.pending.clear() {
+            self.set_slot(owner.id, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::test_components::*;
+
+    const PAGE: u32 = SPARSE_PAGE_SIZE as u32;
+
+    #[test]
+    fn pages_are_only_allocated_when_used() {
+        let s = with_slots(SparseSetStorage::new(), &[PAGE * 3 + 1]);
+
+        assert_eq!(s.sparse.len(), 4);
+        assert_eq!(s.sparse.iter().filter(|p| p.is_some()).count(), 1);
+        assert!(s.fetch(id(1, 1)).is_none());
+        assert!(s.fetch(id(PAGE * 7, 1)).is_none());
+    }
+
+    #[test]
+    fn slots_on_either_side_of_a_page_boundary() {
+        let s = with_slots(SparseSetStorage::new(), &[PAGE - 1, PAGE, 2 * PAGE - 1]);
+
+        for i in [PAGE - 1, PAGE, 2 * PAGE - 1] {
+            assert_eq!(s.fetch(id(i, 1)).unwrap().x, i as i32);
+        }
+        for i in [PAGE - 2, PAGE + 1, 2 * PAGE] {
+            assert!(!s.has_component(id(i, 1)));
+        }
+    }
+
+    #[test]
+    fn swap_removal_repoints_slot_on_another_page() {
+        let mut s = with_slots(SparseSetStorage::new(), &[3, PAGE + 5, 2 * PAGE + 9]);
+
+        s.delete_now(id(3, 1)).unwrap();
+
+        assert_eq!(s.owners(), &[id(2 * PAGE + 9, 1), id(PAGE + 5, 1)]);
+        assert_eq!(s.fetch(id(2 * PAGE + 9, 1)).unwrap().x, (2 * PAGE + 9) as i32);
+        assert_eq!(s.slot(3), None);
+    }
+
+    #[test]
+    fn stale_generations_are_not_found() {
+        let mut s = with_slots(SparseSetStorage::new(), &[1]);
+        let stale = id(1, 2);
+
+        assert!(s.fetch(stale).is_none());
+        assert!(s.fetch_mut(stale).is_none());
+        assert!(!s.has_component(stale));
+        assert!(s.delete(stale).is_err());
+        assert!(s.take(stale).is_err());
+        assert_eq!(s.fetch(id(1, 1)).unwrap().x, 1);
+    }
+
+    #[test]
+    fn other_generation_in_slot_is_kept() {
+        let mut s = with_slots(SparseSetStorage::new(), &[0, 1]);
+
+        assert!(s.insert(id(1, 2), Position::new(id(1, 2), 12)).is_err());
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.fetch(id(1, 1)).unwrap().x, 1);
+        assert!(s.fetch(id(1, 2)).is_none());
+
+        s.delete_now(id(1, 1)).unwrap();
+        s.insert(id(1, 2), Position::new(id(1, 2), 12)).unwrap();
+        assert_eq!(s.fetch(id(1, 2)).unwrap().x, 12);
+    }
+
+    #[test]
+    fn deferred_delete_spares_a_newer_generation() {
+        let mut s = with_slots(SparseSetStorage::new(), &[0, 1]);
+
+        s.delete(id(0, 1)).unwrap();
+        s.insert(id(0, 2), Position::new(id(0, 2), 7)).unwrap();
+        s.update();
+
+        assert_eq!(s.fetch(id(0, 2)).unwrap().x, 7);
+        assert_eq!(s.len(), 2);
+    }
+
+    #[test]
+    fn pending_drops_are_found_through_their_slot() {
+        let mut s = with_slots(SparseSetStorage::new(), &[0, PAGE + 1, 2]);
+
+        for i in [0, PAGE + 1, 2] {
+            s.delete(id(i, 1)).unwrap();
+        }
+        assert_eq!(s.slot(PAGE + 1), Some(Held::Pending(1)));
+
+        //Taking the first pending drop moves the last one into its index.
+        assert_eq!(s.take(id(0, 1)).unwrap().x, 0);
+        assert_eq!(s.slot(2), Some(Held::Pending(0)));
+        assert_eq!(s.fetch_deleting(id(2, 1)).unwrap().x, 2);
+        assert!(s.fetch_deleting(id(2, 2)).is_none());
+
+        s.update();
+        assert_eq!(s.slot(PAGE + 1), None);
+        assert_eq!(s.slot(2), None);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::component::component::*;
+use crate::component::component_manager::*;
+use crate::common::generational_id::*;
+use crate::query::query::*;
+
+use std::vec::Vec;
+use std::any::type_name;
+use std::marker::PhantomData;
+
+///The trait for marker types that hold no data, such as Enemy or Collectible.
+///
+///A Tag is stored in a TagStorage as a single bit per Entity slot, rather than as a Component with
+///an owner. A type should be either a Tag or a Component, never both, since both are registered
+///to the World under the TypeId of the type. Every Tag also implements QueryTarget, so Queries can
+///check for it with QueryElement::has.
+///
+pub trait Tag: QueryTarget {}
+
+///The Component form of the Tag T, used wherever Components are handled as trait objects, such as
+///World::attach_dyn, World::duplicate and World::transfer. A World that registered T routes it to
+///the TagStorage for T.
+pub struct Tagged<T: Tag> {
+    owner: GenerationalId,
+    marker: PhantomData<T>
+}
+
+#[allow(dead_code)]
+impl<T: Tag> Tagged<T> {
+    pub fn new() -> Tagged<T> {
+        Tagged {
+            owner: GenerationalId::new(0, 0),
+            marker: PhantomData
+        }
+    }
+}
+
+impl<T: Tag> Component for Tagged<T> {
+    fn get_owner(&self) -> GenerationalId {
+        self.owner
+    }
+
+    fn set_owner(&mut self, owner: GenerationalId) {
+        self.owner = owner;
+    }
+
+    fn type_name(&self) -> String {
+        type_name::<T>().to_string()
+    }
+
+    fn text_repr(&self) -> String {
+        format!("{{\n\towner: {{\n\t\tid: {},\n\t\tgen: {}\n\t}},\n\ttag: {}\n}}", self.owner.id, self.owner.gen, type_name::<T>())
+    }
+
+    fn dynamic_clone(&self) -> Box<dyn Component> {
+        Box::new(
+            Tagged::<T> {
+                owner: self.owner,
+                marker: PhantomData
+            }
+        )
+    }
+}
+
+///A GeneralComponentManager for the Tag T, storing one bit per Entity slot.
+///
+///has_component is a single bit test on the slot of the GenerationalId, followed by a comparison
+///against the owner that tagged the slot, so a stale GenerationalId can neither see nor clear the
+///Tag of a newer Entity in the same slot. Each slot keeps that owner in a Tagged<T>, which is also
+///what fetch_dyn hands out, and how iter yields the full GenerationalId of every tagged Entity.
+///
+///Tags hold no data that could still be needed after they are removed, so delete untags an Entity
+///right away, the same as delete_now. TagStorages are not wrapped in a TrackedManager, so Tags
+///have no change ticks or hooks.
+///
+pub struct TagStorage<T: Tag> {
+    bits: Vec<u64>,
+    tagged: Vec<Tagged<T>>,
+    count: usize
+}
+
+#[allow(dead_code)]
+impl<T: Tag> TagStorage<T> {
+
+    ///Creates a new, empty TagStorage.
+    pub fn new() -> TagStorage<T> {
+        TagStorage {
+            bits: Vec::new(),
+            tagged: Vec::new(),
+            count: 0
+        }
+    }
+
+    ///Returns the number of tagged Entities.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    ///Returns whether or not no Entities are tagged.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    //Returns whether the slot id is tagged, by any generation.
+    fn bit(&self, id: usize) -> bool {
+        self.bits.get(id / 64).is_some_and(|w| w & (1 << (id % 64)) != 0)
+    }
+
+    ///Checks to see if the provided Entity is tagged.
+    pub fn has_component(&self, owner: GenerationalId) -> bool {
+        let id = owner.id as usize;
+        self.bit(id) && self.tagged[id].owner == owner
+    }
+
+    ///Tags the provided Entity. Fails if its slot is already tagged, by it or another generation.
+    pub fn insert(&mut self, owner: GenerationalId) -> Result<(), String> {
+        let id = owner.id as usize;
+        if self.has_component(owner) {
+            return Err(format!("Cannot attach multiple of the same component to Entity {}", owner.id));
+        }
+        if self.bit(id) {
+            return Err(format!("Entity slot {} is still tagged with {} by generation {}", owner.id, type_name::<T>(), self.tagged[id].owner.gen));
+        }
+
+        if self.bits.len() <= id / 64 {
+            self.bits.resize(id / 64 + 1, 0);
+        }
+        if self.tagged.len() <= id {
+            self.tagged.resize_with(id + 1, Tagged::new);
+        }
+
+        self.bits[id / 64] |= 1 << (id % 64);
+        self.tagged[id].owner = owner;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    ///Untags the provided Entity. There is nothing to drop later, so this is the same as delete_now.
+    pub fn delete(&mut self, owner: GenerationalId) -> Result<(), String> {
+        self.delete_now(owner)
+    }
+
+    ///Untags the provided Entity immediately.
+    pub fn delete_now(&mut self, owner: GenerationalId) -> Result<(), String> {
+        if self.has_component(owner) {
+            let id = owner.id as usize;
+            self.bits[id / 64] &= !(1 << (id % 64));
+            self.count -= 1;
+            Ok(())
+        } else {
+            Err(format!("Entity {} does not have a {}", owner.id, type_name::<T>()))
+        }
+    }
+
+    ///Returns an iterator over the GenerationalIds of every tagged Entity, in order of their ids.
+    pub fn iter(&self) -> impl Iterator<Item=GenerationalId> + '_ {
+        self.bits.iter().enumerate().flat_map(move |(w, bits)| {
+            let mut bits = *bits;
+            std::iter::from_fn(move || {
+                if bits == 0 {
+                    return None;
+                }
+
+                let id = w * 64 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                Some(self.tagged[id].owner)
+            })
+        })
+    }
+
+    ///Does nothing, since every deletion is carried out right away.
+    pub fn update(&mut self) {}
+}
+
+impl<T: Tag> GeneralComponentManager for TagStorage<T> {
+    fn fetch_dyn(&self, owner: GenerationalId) -> Option<Box<& dyn Component>> {
+        if self.has_component(owner) {
+            Some(Box::new(&self.tagged[owner.id as usize]))
+        } else {
+            None
+        }
+    }
+
+    //A Tagged<T> has nothing to change but its owner, which must stay the one that tagged the slot.
+    fn fetch_dyn_mut(&mut self, _owner: GenerationalId) -> Option<Box<&mut dyn Component>> {
+        None
+    }
+
+    fn general_insert(&mut self, owner: GenerationalId, value: Box<dyn Component>) -> Result<(), String> {
+        match value.downcast::<Tagged<T>>() {
+            Ok(_) => self.insert(owner),
+            Err(v) => Err(format!("Cannot insert a {} into a TagStorage for {}", v.type_name(), type_name::<T>()))
+        }
+    }
+
+    fn general_has_component(&self, owner: GenerationalId) -> bool {
+        self.has_component(owner)
+    }
+
+    fn general_delete(&mut self, owner: GenerationalId) -> Result<(), String> {
+        self.delete(owner)
+    }
+
+    fn general_check_insert(&self, _values: &[&dyn Component]) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn general_delete_now(&mut self, owner: GenerationalId) -> Result<(), String> {
+        self.delete_now(owner)
+    }
+
+    fn general_flush(&mut self) {
+        self.update();
+    }
+
+    fn general_update(&mut self) {
+        self.update()
+    }
+
+    fn general_remap(&mut self, remap: &[(GenerationalId, GenerationalId)]) {
+        let moved: Vec<GenerationalId> = remap.iter()
+            .filter(|(old, _)| self.delete_now(*old).is_ok())
+            .map(|(_, new)| *new)
+            .collect();
+
+        for new in moved {
+            let _ = self.insert(new);
+        }
+    }
+}
+
+///Returns the TagStorage for T behind a read guard of the World's ComponentManagers.
+pub fn tag_read_lock<'a, T: Tag>(guard: &'a std::sync::RwLockReadGuard<Box<dyn GeneralComponentManager>>) -> &'a TagStorage<T> {
+    (*guard).downcast_ref::<TagStorage<T>>().unwrap()
+}
+
+///Returns the TagStorage for T behind a write guard of the World's ComponentManagers.
+pub fn tag_write_lock<'a, T: Tag>(guard: &'a mut std::sync::RwLockWriteGuard<Box<dyn GeneralComponentManager>>) -> &'a mut TagStorage<T> {
+    (*guard).downcast_mut::<TagStorage<T>>().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::test_components::*;
+
+    struct Marked;
+    impl Tag for Marked {}
+    impl QueryTarget for Marked {}
+
+    #[test]
+    fn bits_span_several_words() {
+        let mut s = TagStorage::<Marked>::new();
+        for i in [130, 0, 64, 63] {
+            s.insert(id(i, 2)).unwrap();
+        }
+
+        assert_eq!(s.len(), 4);
+        assert_eq!(s.bits.len(), 3);
+        assert_eq!(s.iter().collect::<Vec<_>>(), vec![id(0, 2), id(63, 2), id(64, 2), id(130, 2)]);
+        assert!(!s.has_component(id(65, 1)));
+        assert!(!s.has_component(id(1000, 1)));
+        assert!(s.insert(id(64, 2)).is_err());
+    }
+
+    #[test]
+    fn delete_untags_right_away() {
+        let mut s = TagStorage::<Marked>::new();
+        s.insert(id(5, 1)).unwrap();
+        s.insert(id(6, 1)).unwrap();
+
+        s.delete(id(5, 1)).unwrap();
+        s.delete(id(6, 1)).unwrap();
+        assert!(!s.has_component(id(5, 1)));
+        assert!(s.delete(id(5, 1)).is_err());
+
+        s.insert(id(5, 1)).unwrap();
+        s.insert(id(6, 2)).unwrap();
+        s.update();
+
+        assert_eq!(s.iter().collect::<Vec<_>>(), vec![id(5, 1), id(6, 2)]);
+        assert_eq!(s.len(), 2);
+    }
+
+    #[test]
+    fn stale_generation_leaves_tag_alone() {
+        let mut s = TagStorage::<Marked>::new();
+        s.insert(id(3, 2)).unwrap();
+        let stale = id(3, 1);
+
+        assert!(!s.has_component(stale));
+        assert!(s.delete(stale).is_err());
+        assert!(s.delete_now(stale).is_err());
+        assert!(s.insert(stale).is_err());
+        assert!(s.fetch_dyn(stale).is_none());
+
+        assert!(s.has_component(id(3, 2)));
+        assert_eq!(s.iter().collect::<Vec<_>>(), vec![id(3, 2)]);
+        assert_eq!(s.len(), 1);
+    }
+
+    #[test]
+    fn only_its_own_tagged_proxy_is_inserted() {
+        let mut s = TagStorage::<Marked>::new();
+
+        assert!(s.general_insert(id(0, 1), Box::new(Position::new(id(0, 1), 0))).is_err());
+        assert!(s.general_insert(id(0, 1), Box::new(Tagged::<Marked>::new())).is_ok());
+        assert!(s.general_has_component(id(0, 1)));
+        assert_eq!(s.fetch_dyn(id(0, 1)).unwrap().type_name(), type_name::<Marked>());
+    }
+
+    #[test]
+    fn fetched_proxy_has_the_tagged_owner() {
+        let mut s = TagStorage::<Marked>::new();
+        s.insert(id(2, 3)).unwrap();
+        s.insert(id(9, 1)).unwrap();
+
+        assert_eq!(s.fetch_dyn(id(2, 3)).unwrap().get_owner(), id(2, 3));
+        assert_eq!(s.fetch_dyn(id(9, 1)).unwrap().get_owner(), id(9, 1));
+        assert!(s.fetch_dyn_mut(id(9, 1)).is_none());
+    }
+
+    #[test]
+    fn remap_moves_bits() {
+        let mut s = TagStorage::<Marked>::new();
+        s.insert(id(0, 1)).unwrap();
+        s.insert(id(3, 1)).unwrap();
+
+        s.general_remap(&[(id(3, 1), id(0, 2)), (id(0, 1), id(70, 4))]);
+
+        assert_eq!(s.iter().collect::<Vec<_>>(), vec![id(0, 2), id(70, 4)]);
+        assert_eq!(s.len(), 2);
+    }
+}
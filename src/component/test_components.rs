@@ -0,0 +1,93 @@
+use crate::component::component::*;
+use crate::component::component_manager::*;
+use crate::common::generational_id::*;
+
+///Shorthand for the GenerationalId of slot i at generation gen.
+pub fn id(i: u32, gen: u32) -> GenerationalId {
+    GenerationalId::new(i, gen)
+}
+
+///Returns m with a Position inserted for each of the given slots, in order, owned by the slot's
+///first generation and with x set to the slot.
+pub fn with_slots<M: ComponentManager<Data=Position>>(mut m: M, slots: &[u32]) -> M {
+    for i in slots {
+        m.insert(id(*i, 1), Position::new(id(*i, 1), *i as i32)).unwrap();
+    }
+    m
+}
+
+///A small Component used by the storage tests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    owner: GenerationalId,
+    pub x: i32
+}
+
+impl Position {
+    pub fn new(owner: GenerationalId, x: i32) -> Position {
+        Position {
+            owner,
+            x
+        }
+    }
+}
+
+impl Component for Position {
+    fn get_owner(&self) -> GenerationalId {
+        self.owner
+    }
+
+    fn set_owner(&mut self, owner: GenerationalId) {
+        self.owner = owner;
+    }
+
+    fn type_name(&self) -> String {
+        "Position".to_string()
+    }
+
+    fn text_repr(&self) -> String {
+        format!("{{ x: {} }}", self.x)
+    }
+
+    fn dynamic_clone(&self) -> Box<dyn Component> {
+        Box::new(self.clone())
+    }
+}
+
+///A second small Component, for tests that need Entities with more than one type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Velocity {
+    owner: GenerationalId,
+    pub dx: i32
+}
+
+impl Velocity {
+    pub fn new(owner: GenerationalId, dx: i32) -> Velocity {
+        Velocity {
+            owner,
+            dx
+        }
+    }
+}
+
+impl Component for Velocity {
+    fn get_owner(&self) -> GenerationalId {
+        self.owner
+    }
+
+    fn set_owner(&mut self, owner: GenerationalId) {
+        self.owner = owner;
+    }
+
+    fn type_name(&self) -> String {
+        "Velocity".to_string()
+    }
+
+    fn text_repr(&self) -> String {
+        format!("{{ dx: {} }}", self.dx)
+    }
+
+    fn dynamic_clone(&self) -> Box<dyn Component> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,562 @@
+use crate::component::component::*;
+use crate::component::component_manager::*;
+use crate::common::generational_id::*;
+
+use std::vec::Vec;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+///A callback run with the owner and a reference to a Component when it is added or removed.
+pub type ComponentHook<C> = Box<dyn Fn(GenerationalId, &C) + Send + Sync>;
+
+///The oldest a tick may be, counted back from the current change tick. Older ticks are moved up
+///to this age, so that ticks compare correctly after the change tick wraps around.
+pub const MAX_TICK_AGE: u32 = u32::MAX - 2 * TICK_CHECK_INTERVAL;
+
+//How many ticks may pass before stored ticks are checked against MAX_TICK_AGE again.
+const TICK_CHECK_INTERVAL: u32 = 1 << 29;
+
+///Returns whether tick came after since, both being at most MAX_TICK_AGE older than now.
+pub fn tick_is_newer(tick: u32, since: u32, now: u32) -> bool {
+    now.wrapping_sub(since).min(MAX_TICK_AGE) > now.wrapping_sub(tick).min(MAX_TICK_AGE)
+}
+
+///Returns tick, or the oldest tick allowed by MAX_TICK_AGE if it is older than that.
+pub fn clamp_tick(tick: u32, now: u32) -> u32 {
+    if now.wrapping_sub(tick) > MAX_TICK_AGE {
+        now.wrapping_sub(MAX_TICK_AGE)
+    } else {
+        tick
+    }
+}
+
+///The ticks at which a Component was added to its Entity and last fetched mutably.
+#[derive(Copy, Clone, Debug)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32
+}
+
+//The ticks of the Component attached to the Entity in one slot, if gen is not 0, and whether its
+//deletion has been deferred to the next flush.
+#[derive(Copy, Clone, Debug)]
+struct TickSlot {
+    gen: u32,
+    ticks: ComponentTicks,
+    pending: bool
+}
+
+///A ComponentManager wrapping any other ComponentManager to track when its Components change.
+///
+///The World wraps every ComponentManager it registers in a TrackedManager, which stamps each
+///Component with the World's change tick when it is inserted, and again whenever it is fetched
+///mutably, either through fetch_mut, iter_mut or as_mut_slice. Systems compare these ticks
+///against the tick of their last run to find only the Components that were added or changed since.
+///
+///A TrackedManager also runs the lifecycle hooks registered for its Component type: on_add hooks
+///after a Component is inserted, and on_remove hooks exactly once per removal, with the Component
+///itself just before it is dropped, whether it is removed immediately or by the update that carries
+///out its deferred deletion.
+///
+///Ticks are stored by Entity slot, and compared by their age relative to the current change tick,
+///so they stay correct after it wraps around as long as update runs at least once in every few
+///hundred million ticks.
+///
+///Finally, it records the owner of every Component removed between two updates, so Systems that
+///keep caches keyed by GenerationalId can clean them up during the following frame.
+///
+pub struct TrackedManager<C: Component> {
+    inner: Box<dyn ComponentManager<Data=C>>,
+    ticks: Vec<TickSlot>,
+    checked_at: u32,
+    pending: Vec<GenerationalId>,
+    removing: Vec<GenerationalId>,
+    removed: Vec<GenerationalId>,
+    tick: Arc<AtomicU32>,
+    on_add: Vec<ComponentHook<C>>,
+    on_remove: Vec<ComponentHook<C>>
+}
+
+#[allow(dead_code)]
+impl<C: Component> TrackedManager<C> {
+
+    ///Wraps the provided ComponentManager, reading the current change tick from tick.
+    pub fn new<M: ComponentManager<Data=C>>(inner: M, tick: Arc<AtomicU32>) -> TrackedManager<C> {
+        TrackedManager {
+            inner: Box::new(inner),
+            ticks: Vec::new(),
+            checked_at: 0,
+            pending: Vec::new(),
+            removing: Vec::new(),
+            removed: Vec::new(),
+            tick,
+            on_add: Vec::new(),
+            on_remove: Vec::new()
+        }
+    }
+
+    ///Returns an immutable reference to the wrapped ComponentManager.
+    pub fn inner(&self) -> &dyn ComponentManager<Data=C> {
+        &*self.inner
+    }
+
+    ///Returns a mutable reference to the wrapped ComponentManager. Nothing fetched through it is
+    ///marked as changed.
+    pub fn inner_mut(&mut self) -> &mut dyn ComponentManager<Data=C> {
+        &mut *self.inner
+    }
+
+    ///Registers a hook to run after a Component is inserted.
+    pub fn on_add(&mut self, hook: ComponentHook<C>) {
+        self.on_add.push(hook);
+    }
+
+    ///Registers a hook to run just before a Component is removed.
+    pub fn on_remove(&mut self, hook: ComponentHook<C>) {
+        self.on_remove.push(hook);
+    }
+
+    ///Returns the owners of every Component removed between the last two updates, in the order
+    ///they were removed.
+    pub fn removed(&self) -> &[GenerationalId] {
+        &self.removed
+    }
+
+    ///Returns the ticks of the Component attached to owner, if it exists.
+    pub fn ticks(&self, owner: GenerationalId) -> Option<ComponentTicks> {
+        match self.ticks.get(owner.id as usize) {
+            Some(s) if s.gen == owner.gen && s.gen != 0 => Some(s.ticks),
+            _ => None
+        }
+    }
+
+    ///Returns the owners of every Component added after the given tick, in order of their ids.
+    pub fn added_since(&self, tick: u32) -> Vec<GenerationalId> {
+        let now = self.now();
+        self.owners_where(|t| tick_is_newer(t.added, tick, now))
+    }
+
+    ///Returns the owners of every Component added or changed after the given tick, in order of
+    ///their ids.
+    pub fn changed_since(&self, tick: u32) -> Vec<GenerationalId> {
+        let now = self.now();
+        self.owners_where(|t| tick_is_newer(t.changed, tick, now))
+    }
+
+    fn owners_where<F: Fn(&ComponentTicks) -> bool>(&self, f: F) -> Vec<GenerationalId> {
+        self.ticks.iter().enumerate()
+            .filter(|(_, s)| s.gen != 0 && f(&s.ticks))
+            .map(|(i, s)| GenerationalId::new(i as u32, s.gen))
+            .collect()
+    }
+
+    fn now(&self) -> u32 {
+        self.tick.load(Ordering::Relaxed)
+    }
+
+    fn set_ticks(&mut self, owner: GenerationalId, ticks: ComponentTicks) {
+        let i = owner.id as usize;
+        if i >= self.ticks.len() {
+            self.ticks.resize(i + 1, TickSlot { gen: 0, ticks, pending: false });
+        }
+        self.ticks[i] = TickSlot { gen: owner.gen, ticks, pending: false };
+    }
+
+    //Forgets the ticks of owner, returning whether there were any.
+    fn clear_ticks(&mut self, owner: GenerationalId) -> bool {
+        match self.ticks.get_mut(owner.id as usize) {
+            Some(s) if s.gen == owner.gen && s.gen != 0 => {
+                s.gen = 0;
+                s.pending = false;
+                true
+            },
+            _ => false
+        }
+    }
+
+    //Returns whether the deletion of the Component of owner has been deferred to the next flush.
+    fn is_pending(&self, owner: GenerationalId) -> bool {
+        match self.ticks.get(owner.id as usize) {
+            Some(s) if s.gen == owner.gen && s.gen != 0 => s.pending,
+            _ => false
+        }
+    }
+
+    //Sets whether the deletion of the Component of owner is deferred, returning whether it was.
+    fn set_pending(&mut self, owner: GenerationalId, pending: bool) -> bool {
+        match self.ticks.get_mut(owner.id as usize) {
+            Some(s) if s.gen == owner.gen && s.gen != 0 => std::mem::replace(&mut s.pending, pending),
+            _ => false
+        }
+    }
+
+    ///Marks the Component of owner as changed at the current change tick, for Components changed
+    ///through inner_mut.
+    pub fn mark_changed(&mut self, owner: GenerationalId) {
+        let now = self.now();
+        if let Some(s) = self.ticks.get_mut(owner.id as usize) {
+            if s.gen == owner.gen {
+                s.ticks.changed = now;
+            }
+        }
+    }
+
+    fn mark_all_changed(&mut self) {
+        let now = self.now();
+        for s in self.ticks.iter_mut() {
+            s.ticks.changed = now;
+        }
+    }
+
+    //Moves every tick older than MAX_TICK_AGE up to it, once every TICK_CHECK_INTERVAL ticks.
+    fn check_ticks(&mut self) {
+        let now = self.now();
+        if now.wrapping_sub(self.checked_at) < TICK_CHECK_INTERVAL {
+            return;
+        }
+
+        for s in self.ticks.iter_mut() {
+            s.ticks.added = clamp_tick(s.ticks.added, now);
+            s.ticks.changed = clamp_tick(s.ticks.changed, now);
+        }
+        self.checked_at = now;
+    }
+
+    //Returns the Component of owner that is still stored, even if its deletion has been deferred.
+    fn stored(&self, owner: GenerationalId) -> Option<&C> {
+        self.inner.fetch(owner).or_else(|| self.inner.fetch_deleting(owner))
+    }
+
+    fn run_hooks(hooks: &[ComponentHook<C>], owner: GenerationalId, comp: Option<&C>) {
+        if let Some(c) = comp {
+            for h in hooks.iter() {
+                h(owner, c);
+            }
+        }
+    }
+}
+
+impl<C: Component> std::fmt::Debug for TrackedManager<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackedManager")
+            .field("inner", &self.inner)
+            .field("ticks", &self.ticks)
+            .finish()
+    }
+}
+
+impl<C: Component> ComponentManager for TrackedManager<C> {
+    type Data = C;
+
+    fn iter(&self) -> ComponentIter<'_, C> {
+        self.inner.iter()
+    }
+
+    fn iter_mut(&mut self) -> ComponentIterMut<'_, C> {
+        self.mark_all_changed();
+        self.inner.iter_mut()
+    }
+
+    fn as_slice(&self) -> Option<&[C]> {
+        self.inner.as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [C]> {
+        if self.inner.as_slice().is_some() {
+            self.mark_all_changed();
+        }
+        self.inner.as_mut_slice()
+    }
+
+    fn fetch(&self, owner: GenerationalId) -> Option<&C> {
+        self.inner.fetch(owner)
+    }
+
+    fn fetch_mut(&mut self, owner: GenerationalId) -> Option<&mut C> {
+        self.mark_changed(owner);
+        self.inner.fetch_mut(owner)
+    }
+
+    fn fetch_deleting(&self, owner: GenerationalId) -> Option<&C> {
+        self.inner.fetch_deleting(owner)
+    }
+
+    fn has_component(&self, owner: GenerationalId) -> bool {
+        self.inner.has_component(owner)
+    }
+
+    fn insert(&mut self, owner: GenerationalId, value: C) -> Result<(), String> {
+        //A Component of owner whose deletion is still deferred is removed for good first, so the
+        //new one can take its place.
+        if self.is_pending(owner) && !self.inner.has_component(owner) {
+            self.delete_now(owner)?;
+        }
+
+        self.inner.insert(owner, value)?;
+
+        let now = self.now();
+        self.set_ticks(owner, ComponentTicks {
+            added: now,
+            changed: now
+        });
+
+        Self::run_hooks(&self.on_add, owner, self.inner.fetch(owner));
+        Ok(())
+    }
+
+    fn check_insert(&self, values: &[&C]) -> Result<(), String> {
+        self.inner.check_insert(values)
+    }
+
+    fn delete(&mut self, owner: GenerationalId) -> Result<(), String> {
+        self.inner.delete(owner)?;
+        if !self.set_pending(owner, true) {
+            self.pending.push(owner);
+        }
+        Ok(())
+    }
+
+    fn delete_now(&mut self, owner: GenerationalId) -> Result<(), String> {
+        Self::run_hooks(&self.on_remove, owner, self.stored(owner));
+        self.inner.delete_now(owner)?;
+        self.clear_ticks(owner);
+        self.removing.push(owner);
+        Ok(())
+    }
+
+    fn take(&mut self, owner: GenerationalId) -> Result<C, String> {
+        Self::run_hooks(&self.on_remove, owner, self.stored(owner));
+        let c = self.inner.take(owner)?;
+        self.clear_ticks(owner);
+        self.removing.push(owner);
+        Ok(c)
+    }
+
+    fn update(&mut self) {
+        self.flush();
+        self.check_ticks();
+        self.removed = std::mem::take(&mut self.removing);
+    }
+
+    fn flush(&mut self) {
+        //Owners whose deferred deletion was carried out early no longer have their flag set, and
+        //an owner deleted again since then is listed twice but only kept once.
+        let pending: Vec<GenerationalId> = std::mem::take(&mut self.pending).into_iter()
+            .filter(|o| self.set_pending(*o, false))
+            .collect();
+
+        //Every storage removes exactly the owners it was asked to delete, so the hooks are run on
+        //each of their Components while it is still stored.
+        for o in pending.iter() {
+            Self::run_hooks(&self.on_remove, *o, self.stored(*o));
+        }
+
+        self.inner.update();
+
+        for o in pending {
+            if self.clear_ticks(o) {
+                self.removing.push(o);
+            }
+        }
+    }
+
+    fn remap(&mut self, remap: &[(GenerationalId, GenerationalId)]) {
+        self.inner.remap(remap);
+
+        let moved: Vec<(GenerationalId, Option<ComponentTicks>)> = remap.iter()
+            .map(|(old, new)| {
+                let t = self.ticks(*old);
+                self.clear_ticks(*old);
+                (*new, t)
+            })
+            .collect();
+
+        for (new, t) in moved {
+            if let Some(t) = t {
+                self.set_ticks(new, t);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::vec_storage::*;
+    use crate::component::test_components::*;
+    use std::sync::Mutex;
+
+    fn tracked(start: u32) -> (TrackedManager<Position>, Arc<AtomicU32>) {
+        let tick = Arc::new(AtomicU32::new(start));
+        (TrackedManager::new(VecStorage::<Position>::new(), tick.clone()), tick)
+    }
+
+    fn gid(id: u32, gen: u32) -> GenerationalId {
+        GenerationalId::new(id, gen)
+    }
+
+    #[test]
+    fn ticks_compare_across_wraparound() {
+        let (mut m, tick) = tracked(u32::MAX - 1);
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 0)).unwrap();
+
+        tick.store(2, Ordering::Relaxed);
+        m.insert(gid(1, 1), Position::new(gid(1, 1), 0)).unwrap();
+
+        assert_eq!(m.added_since(u32::MAX - 1), vec![gid(1, 1)]);
+        assert_eq!(m.added_since(u32::MAX - 5), vec![gid(0, 1), gid(1, 1)]);
+        assert!(m.changed_since(2).is_empty());
+
+        tick.store(3, Ordering::Relaxed);
+        m.fetch_mut(gid(0, 1)).unwrap();
+        assert_eq!(m.changed_since(2), vec![gid(0, 1)]);
+    }
+
+    #[test]
+    fn old_ticks_are_clamped_on_update() {
+        let (mut m, tick) = tracked(1);
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 0)).unwrap();
+
+        let now = 1u32.wrapping_add(MAX_TICK_AGE).wrapping_add(TICK_CHECK_INTERVAL);
+        tick.store(now, Ordering::Relaxed);
+        m.update();
+
+        assert_eq!(m.ticks(gid(0, 1)).unwrap().added, now.wrapping_sub(MAX_TICK_AGE));
+        assert!(m.added_since(now.wrapping_sub(MAX_TICK_AGE)).is_empty());
+
+        let later = now.wrapping_add(TICK_CHECK_INTERVAL);
+        tick.store(later, Ordering::Relaxed);
+        m.insert(gid(1, 1), Position::new(gid(1, 1), 0)).unwrap();
+        assert_eq!(m.added_since(now), vec![gid(1, 1)]);
+    }
+
+    #[test]
+    fn ticks_belong_to_one_generation() {
+        let (mut m, tick) = tracked(1);
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 0)).unwrap();
+        m.delete_now(gid(0, 1)).unwrap();
+
+        tick.store(5, Ordering::Relaxed);
+        m.insert(gid(0, 2), Position::new(gid(0, 2), 0)).unwrap();
+
+        assert!(m.ticks(gid(0, 1)).is_none());
+        assert_eq!(m.ticks(gid(0, 2)).unwrap().added, 5);
+        assert_eq!(m.added_since(0), vec![gid(0, 2)]);
+        assert!(m.fetch_mut(gid(0, 1)).is_none());
+        assert_eq!(m.ticks(gid(0, 2)).unwrap().changed, 5);
+    }
+
+    //Registers hooks logging every add and remove, with the x of the Component given to them.
+    fn logged(m: &mut TrackedManager<Position>) -> Arc<Mutex<Vec<String>>> {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (add, rm) = (log.clone(), log.clone());
+        m.on_add(Box::new(move |o, p| add.lock().unwrap().push(format!("add {} {}", o.id, p.x))));
+        m.on_remove(Box::new(move |o, p| rm.lock().unwrap().push(format!("rm {} {}", o.id, p.x))));
+        log
+    }
+
+    #[test]
+    fn hooks_see_each_add_and_removal_once() {
+        let (mut m, _) = tracked(1);
+        let log = logged(&mut m);
+        for i in 0..3 {
+            m.insert(gid(i, 1), Position::new(gid(i, 1), i as i32)).unwrap();
+        }
+
+        m.delete_now(gid(0, 1)).unwrap();
+        m.take(gid(1, 1)).unwrap();
+        m.delete(gid(2, 1)).unwrap();
+        m.delete(gid(2, 1)).unwrap();
+        assert_eq!(log.lock().unwrap().len(), 5);
+
+        m.update();
+        m.update();
+        assert_eq!(*log.lock().unwrap(), vec!["add 0 0", "add 1 1", "add 2 2", "rm 0 0", "rm 1 1", "rm 2 2"]);
+    }
+
+    #[test]
+    fn deferred_delete_undone_by_take_runs_hooks_once() {
+        let (mut m, _) = tracked(1);
+        let log = logged(&mut m);
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 7)).unwrap();
+
+        m.delete(gid(0, 1)).unwrap();
+        m.take(gid(0, 1)).unwrap();
+        m.update();
+
+        assert_eq!(*log.lock().unwrap(), vec!["add 0 7", "rm 0 7"]);
+        assert_eq!(m.removed(), &[gid(0, 1)]);
+    }
+
+    #[test]
+    fn owner_deleted_again_after_a_take_is_flushed_once() {
+        let (mut m, _) = tracked(1);
+        let log = logged(&mut m);
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 1)).unwrap();
+
+        m.delete(gid(0, 1)).unwrap();
+        m.take(gid(0, 1)).unwrap();
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 2)).unwrap();
+        m.delete(gid(0, 1)).unwrap();
+        m.update();
+
+        assert_eq!(*log.lock().unwrap(), vec!["add 0 1", "rm 0 1", "add 0 2", "rm 0 2"]);
+        assert_eq!(m.removed(), &[gid(0, 1), gid(0, 1)]);
+        assert!(m.ticks(gid(0, 1)).is_none());
+    }
+
+    #[test]
+    fn deferred_remove_hook_is_given_the_stored_component() {
+        let (mut m, _) = tracked(1);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let s = seen.clone();
+        m.on_remove(Box::new(move |_, p: &Position| s.lock().unwrap().push(p as *const Position as usize)));
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 0)).unwrap();
+
+        m.delete(gid(0, 1)).unwrap();
+        let stored = m.stored(gid(0, 1)).unwrap() as *const Position as usize;
+        m.update();
+
+        assert_eq!(*seen.lock().unwrap(), vec![stored]);
+        assert_eq!(m.removed(), &[gid(0, 1)]);
+    }
+
+    #[test]
+    fn reinserting_a_deleted_owner_removes_the_old_component_first() {
+        let (mut m, tick) = tracked(1);
+        let log = logged(&mut m);
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 1)).unwrap();
+
+        m.delete(gid(0, 1)).unwrap();
+        assert!(m.fetch(gid(0, 1)).is_none());
+        tick.store(4, Ordering::Relaxed);
+        m.insert(gid(0, 1), Position::new(gid(0, 1), 2)).unwrap();
+        m.update();
+
+        assert_eq!(*log.lock().unwrap(), vec!["add 0 1", "rm 0 1", "add 0 2"]);
+        assert_eq!(m.fetch(gid(0, 1)).unwrap().x, 2);
+        assert_eq!(m.ticks(gid(0, 1)).unwrap().added, 4);
+        assert_eq!(m.removed(), &[gid(0, 1)]);
+    }
+
+    #[test]
+    fn removed_lasts_for_one_update() {
+        let (mut m, _) = tracked(1);
+        for i in 0..2 {
+            m.insert(gid(i, 1), Position::new(gid(i, 1), 0)).unwrap();
+        }
+
+        m.delete_now(gid(1, 1)).unwrap();
+        m.delete(gid(0, 1)).unwrap();
+        assert!(m.removed().is_empty());
+
+        m.update();
+        assert_eq!(m.removed(), &[gid(1, 1), gid(0, 1)]);
+
+        m.flush();
+        assert_eq!(m.removed(), &[gid(1, 1), gid(0, 1)]);
+
+        m.update();
+        assert!(m.removed().is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::component::component::*;
+use crate::component::component_manager::*;
+use crate::component::pending_drops::*;
+use crate::common::generational_id::*;
+
+use std::vec::Vec;
+use std::collections::HashMap;
+
+///A general purpose ComponentManager storing any Component type densely in a Vec.
+///
+///Components are kept in one contiguous Vec, so iter and iter_mut walk a plain slice, which is
+///also available directly through as_slice and as_mut_slice, with a map from owner to index
+///for fetching a single Component. Removal swaps the last Component into the freed index, so it
+///takes constant time but does not keep Components in insertion order. A deferred delete takes the
+///Component out of the Vec right away, so it can no longer be fetched or iterated, and only drops
+///it on the next update.
+///Registering a new Component type only takes
+///
+///    world.register_manager(VecStorage::<MyComponent>::new());
+///
+#[derive(Debug)]
+pub struct VecStorage<T: Component> {
+    indir_map: HashMap<GenerationalId, Held>,
+    components: Vec<T>,
+    owners: Vec<GenerationalId>,
+    pending: PendingDrops<T>
+}
+
+#[allow(dead_code)]
+impl<T: Component> VecStorage<T> {
+
+    ///Creates a new, empty VecStorage.
+    pub fn new() -> VecStorage<T> {
+        VecStorage {
+            indir_map: HashMap::new(),
+            components: Vec::new(),
+            owners: Vec::new(),
+            pending: PendingDrops::new()
+        }
+    }
+
+    ///Returns the number of Components stored.
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    ///Returns whether or not no Components are stored.
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    //Removes the Component at index ind by swapping the last Component into its place.
+    fn remove_at(&mut self, ind: usize) -> T {
+        let comp = self.components.swap_remove(ind);
+        let owner = self.owners.swap_remove(ind);
+        self.indir_map.remove(&owner);
+
+        if ind < self.owners.len() {
+            self.indir_map.insert(self.owners[ind], Held::Live(ind));
+        }
+
+        comp
+    }
+
+    //Takes the Component at index ind of the pending drops back out.
+    fn take_pending(&mut self, ind: usize) -> T {
+        let owner = self.pending.owner(ind);
+        let (comp, moved) = self.pending.take(ind);
+        self.indir_map.remove(&owner);
+
+        if let Some(moved) = moved {
+            self.indir_map.insert(moved, Held::Pending(ind));
+        }
+
+        comp
+    }
+}
+
+impl<T: Component + std::fmt::Debug> ComponentManager for VecStorage<T> {
+    type Data = T;
+
+    fn iter(&self) -> ComponentIter<'_, T> {
+        Box::new(self.components.iter())
+    }
+
+    fn iter_mut(&mut self) -> ComponentIterMut<'_, T> {
+        Box::new(self.components.iter_mut())
+    }
+
+    fn as_slice(&self) -> Option<&[T]> {
+        Some(&self.components)
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [T]> {
+        Some(&mut self.components)
+    }
+
+    fn fetch(&self, owner: GenerationalId) -> Option<&T> {
+        match self.indir_map.get(&owner) {
+            Some(Held::Live(i)) => Some(&self.components[*i]),
+            _ => None
+        }
+    }
+
+    fn fetch_mut(&mut self, owner: GenerationalId) -> Option<&mut T> {
+        match self.indir_map.get(&owner) {
+            Some(Held::Live(i)) => Some(&mut self.components[*i]),
+            _ => None
+        }
+    }
+
+    fn fetch_deleting(&self, owner: GenerationalId) -> Option<&T> {
+        match self.indir_map.get(&owner) {
+            Some(Held::Pending(i)) => Some(self.pending.get(*i)),
+            _ => None
+        }
+    }
+
+    fn has_component(&self, owner: GenerationalId) -> bool {
+        matches!(self.indir_map.get(&owner), Some(Held::Live(_)))
+    }
+
+    fn insert(&mut self, owner: GenerationalId, value: T) -> Result<(), String> {
+        match self.indir_map.get(&owner) {
+            Some(Held::Live(_)) => {
+                return Err(format!("Cannot attach multiple of the same component to Entity {}", owner.id));
+            },
+            Some(Held::Pending(i)) => {
+                let i = *i;
+                self.take_pending(i);
+            },
+            None => {}
+        }
+
+        self.indir_map.insert(owner, Held::Live(self.components.len()));
+        self.components.push(value);
+        self.owners.push(owner);
+
+        Ok(())
+    }
+
+    fn delete(&mut self, owner: GenerationalId) -> Result<(), String> {
+        match self.indir_map.get(&owner) {
+            Some(Held::Live(i)) => {
+                let i = *i;
+                let comp = self.remove_at(i);
+                let i = self.pending.hold(owner, comp);
+                self.indir_map.insert(owner, Held::Pending(i));
+                Ok(())
+            },
+            Some(Held::Pending(_)) => Ok(()),
+            None => Err(format!("Entity {} does not have a {}", owner.id, std::any::type_name::<T>()))
+        }
+    }
+
+    fn delete_now(&mut self, owner: GenerationalId) -> Result<(), String> {
+        self.take(owner).map(|_| ())
+    }
+
+    fn take(&mut self, owner: GenerationalId) -> Result<T, String> {
+        match self.indir_map.get(&owner) {
+            Some(Held::Live(i)) => {
+                let i = *i;
+                Ok(self.remove_at(i))
+            },
+            Some(Held::Pending(i)) => {
+                let i = *i;
+                Ok(self.take_pending(i))
+            },
+            None => Err(format!("Entity {} does not have a {}", owner.id, std::any::type_name::<T>()))
+        }
+    }
+
+    fn update(&mut self) {
+        for owner in self.pending.clear() {
+            self.indir_map.remove(&owner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::test_components::*;
+
+    fn xs(s: &VecStorage<Position>) -> Vec<i32> {
+        s.as_slice().unwrap().iter().map(|p| p.x).collect()
+    }
+
+    #[test]
+    fn slice_is_in_insertion_order_until_a_removal() {
+        let mut s = with_slots(VecStorage::new(), &[7, 2, 5, 0]);
+        assert_eq!(xs(&s), vec![7, 2, 5, 0]);
+
+        s.delete_now(id(7, 1)).unwrap();
+        assert_eq!(xs(&s), vec![0, 2, 5]);
+
+        assert_eq!(s.take(id(5, 1)).unwrap().x, 5);
+        assert_eq!(xs(&s), vec![0, 2]);
+        assert_eq!(s.fetch(id(0, 1)).unwrap().x, 0);
+    }
+
+    #[test]
+    fn deferred_deletes_follow_swapped_components() {
+        let mut s = with_slots(VecStorage::new(), &[0, 1, 2, 3]);
+
+        //Removing slot 0 first moves slot 3 into its index, where it must still be found.
+        s.delete(id(0, 1)).unwrap();
+        s.delete(id(3, 1)).unwrap();
+        s.delete(id(0, 1)).unwrap();
+        s.update();
+
+        assert_eq!(xs(&s), vec![2, 1]);
+        assert!(!s.has_component(id(3, 1)));
+        assert_eq!(s.fetch(id(2, 1)).unwrap().x, 2);
+    }
+
+    #[test]
+    fn deferred_delete_hides_component_until_dropped() {
+        let mut s = with_slots(VecStorage::new(), &[0, 1, 2]);
+
+        s.delete(id(1, 1)).unwrap();
+
+        assert!(s.fetch(id(1, 1)).is_none());
+        assert!(!s.has_component(id(1, 1)));
+        assert_eq!(xs(&s), vec![0, 2]);
+        assert_eq!(s.iter().count(), 2);
+        assert_eq!(s.fetch_deleting(id(1, 1)).unwrap().x, 1);
+        assert!(s.delete(id(1, 1)).is_ok());
+
+        s.update();
+        assert!(s.fetch_deleting(id(1, 1)).is_none());
+        assert!(s.delete(id(1, 1)).is_err());
+    }
+
+    #[test]
+    fn insert_replaces_component_waiting_to_be_dropped() {
+        let mut s = with_slots(VecStorage::new(), &[0]);
+
+        s.delete(id(0, 1)).unwrap();
+        s.insert(id(0, 1), Position::new(id(0, 1), 9)).unwrap();
+        s.update();
+
+        assert_eq!(xs(&s), vec![9]);
+        assert!(s.fetch_deleting(id(0, 1)).is_none());
+    }
+
+    #[test]
+    fn generations_of_one_slot_are_separate_owners() {
+        let mut s = with_slots(VecStorage::new(), &[0]);
+        s.insert(id(0, 2), Position::new(id(0, 2), 20)).unwrap();
+
+        assert_eq!(s.len(), 2);
+        assert_eq!(s.fetch(id(0, 1)).unwrap().x, 0);
+        assert_eq!(s.fetch(id(0, 2)).unwrap().x, 20);
+
+        s.delete(id(0, 1)).unwrap();
+        s.update();
+        assert_eq!(xs(&s), vec![20]);
+    }
+
+    #[test]
+    fn take_cancels_pending_delete() {
+        let mut s = with_slots(VecStorage::new(), &[0, 1]);
+
+        s.delete(id(0, 1)).unwrap();
+        assert_eq!(s.take(id(0, 1)).unwrap().x, 0);
+        s.insert(id(0, 1), Position::new(id(0, 1), 5)).unwrap();
+        s.update();
+
+        assert_eq!(s.fetch(id(0, 1)).unwrap().x, 5);
+    }
+
+    #[test]
+    fn remap_swaps_owners() {
+        let mut s = with_slots(VecStorage::new(), &[0, 1, 2]);
+
+        s.remap(&[(id(0, 1), id(1, 1)), (id(1, 1), id(0, 2))]);
+
+        assert_eq!(s.fetch(id(1, 1)).unwrap().x, 0);
+        assert_eq!(s.fetch(id(1, 1)).unwrap().get_owner(), id(1, 1));
+        assert_eq!(s.fetch(id(0, 2)).unwrap().x, 1);
+        assert!(!s.has_component(id(0, 1)));
+        assert_eq!(s.len(), 3);
+    }
+}
@@ -13,6 +13,8 @@ use std::cmp::{PartialEq, PartialOrd, Eq, Ord, Ordering};
 #[repr(C)]
 pub struct Entity {
     pub id: GenerationalId,
+    pub parent: Option<GenerationalId>,
+    pub enabled: bool
 }
 
 impl Entity {
@@ -21,6 +23,8 @@ impl Entity {
     pub fn new(gen_id: GenerationalId) -> Entity {
         Entity {
             id: gen_id,
+            parent: None,
+            enabled: true
         }
     }
 }
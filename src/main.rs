@@ -30,7 +30,7 @@ fn main() {
 
     println!("\n\nHandle 1: {:?}\nHandle 2: {:?}\nHandle 3: {:?}\n\n", handle_1, handle_2, handle_3);
 
-    sys_man.execute(&mut world, 0.016f32);
+    sys_man.execute(&mut world, 0.016f32).unwrap();
 
     println!("\n\nDeleting 2 Entities...");
 
@@ -45,7 +45,7 @@ fn main() {
 
     println!("Executing SystemManager...\n");
 
-    sys_man.execute(&mut world, 0.016f32);
+    sys_man.execute(&mut world, 0.016f32).unwrap();
 
     println!("Enabling NameSystem...\n");
 
@@ -53,7 +53,7 @@ fn main() {
 
     println!("Executing SystemManager...\n");
 
-    sys_man.execute(&mut world, 0.016f32);
+    sys_man.execute(&mut world, 0.016f32).unwrap();
 
     println!("\nSpawning 3 Entities...\n");
 
@@ -65,7 +65,7 @@ fn main() {
 
     println!("\n\nHandle 1: {:?}\nHandle 2: {:?}\nHandle 3: {:?}\nHandle 4: {:?}\n\n", handle_1, handle_2, handle_3, handle_4);
 
-    sys_man.execute(&mut world, 0.016f32);
+    sys_man.execute(&mut world, 0.016f32).unwrap();
 
     println!("\n");
 
@@ -5,7 +5,6 @@ use crate::common::generational_id::*;
 use std::vec::*;
 use std::collections::HashMap;
 use std::string::*;
-use std::slice::{Iter, IterMut};
 use std::option::*;
 
 #[derive(Clone, Debug)]
@@ -56,28 +55,157 @@ impl Component for NameComponent {
 pub struct NameComponentManager {
     indir_map: HashMap<GenerationalId, usize>,
     components: Vec<NameComponent>,
-    to_delete: Vec<usize>
+    to_delete: Vec<usize>,
+    name_index: HashMap<String, Vec<GenerationalId>>,
+    conflicts: Vec<GenerationalId>,
+    unique: bool,
+    dirty: bool
 }
 
+#[allow(dead_code)]
 impl NameComponentManager {
     pub fn new() -> NameComponentManager {
         NameComponentManager {
             indir_map: HashMap::new(),
             components: Vec::new(),
-            to_delete: Vec::new()
+            to_delete: Vec::new(),
+            name_index: HashMap::new(),
+            conflicts: Vec::new(),
+            unique: false,
+            dirty: false
         }
     }
+
+    ///Creates a NameComponentManager that rejects any NameComponent whose name is already in use.
+    pub fn new_unique() -> NameComponentManager {
+        let mut man = NameComponentManager::new();
+        man.unique = true;
+        man
+    }
+
+    ///Returns the first Entity inserted with the given name, if any.
+    ///
+    ///Names changed through fetch_mut or iter_mut are only reindexed on the next update, and
+    ///deleted Components can still be found until then, just as they can still be fetched.
+    pub fn find(&self, name: &str) -> Option<GenerationalId> {
+        self.find_all(name).first().copied()
+    }
+
+    ///Returns every Entity with the given name, in the order they were inserted.
+    ///
+    ///Names changed through fetch_mut or iter_mut are only reindexed on the next update, and
+    ///deleted Components can still be found until then, just as they can still be fetched.
+    pub fn find_all(&self, name: &str) -> &[GenerationalId] {
+        match self.name_index.get(name) {
+            Some(v) => v,
+            None => &[]
+        }
+    }
+
+    //Returns whether or not a Component that is not waiting to be deleted has the given name,
+    //including names changed since the last update.
+    fn name_taken(&self, name: &str) -> bool {
+        if self.dirty {
+            self.components.iter().enumerate().any(|(i, c)| c.name == name && !self.to_delete.contains(&i))
+        } else {
+            self.find_all(name).iter().any(|o| !self.is_deleting(*o))
+        }
+    }
+
+    //Returns whether or not the Component of owner is waiting to be deleted on the next update.
+    fn is_deleting(&self, owner: GenerationalId) -> bool {
+        self.indir_map.get(&owner).is_some_and(|i| self.to_delete.contains(i))
+    }
+
+    fn unindex(&mut self, name: &str, owner: GenerationalId) {
+        if let Some(v) = self.name_index.get_mut(name) {
+            v.retain(|o| *o != owner);
+            if v.is_empty() {
+                self.name_index.remove(name);
+            }
+        }
+    }
+
+    ///Returns the Entities that were renamed to a name already in use, as of the last time names
+    ///were reindexed. Only a unique NameComponentManager has conflicts, and none of these
+    ///Entities can be found by their name until they are renamed again.
+    pub fn conflicts(&self) -> &[GenerationalId] {
+        &self.conflicts
+    }
+
+    //Rebuilds the name index from every Component. In unique mode, among the Components not
+    //waiting to be deleted, an Entity that already held a name keeps it in the index, and any other
+    //Entity now using that name is recorded as a conflict instead.
+    fn reindex(&mut self) {
+        let old = std::mem::take(&mut self.name_index);
+        self.conflicts.clear();
+
+        let mut deleting = Vec::new();
+        for (i, c) in self.components.iter().enumerate() {
+            self.name_index.entry(c.name.clone()).or_default().push(c.owner);
+            if self.to_delete.contains(&i) {
+                deleting.push(c.owner);
+            }
+        }
+
+        if self.unique {
+            for (name, owners) in self.name_index.iter_mut() {
+                let live: Vec<GenerationalId> = owners.iter().filter(|o| !deleting.contains(o)).copied().collect();
+                if live.len() > 1 {
+                    let kept = old.get(name)
+                        .and_then(|prev| live.iter().find(|o| prev.contains(o)))
+                        .unwrap_or(&live[0]);
+                    let kept = *kept;
+
+                    self.conflicts.extend(live.iter().filter(|o| **o != kept));
+                    owners.retain(|o| *o == kept || deleting.contains(o));
+                }
+            }
+        }
+
+        self.dirty = false;
+    }
+
+    //Removes the Component at index ind by swapping the last Component into its place, then
+    //points the moved Component's owner and any pending deletion of it at ind.
+    fn remove_at(&mut self, ind: usize) -> NameComponent {
+        let comp = self.components.swap_remove(ind);
+        self.indir_map.remove(&comp.owner);
+        self.unindex(&comp.name, comp.owner);
+
+        let last = self.components.len();
+        if ind < last {
+            self.indir_map.insert(self.components[ind].owner, ind);
+            for i in self.to_delete.iter_mut() {
+                if *i == last {
+                    *i = ind;
+                }
+            }
+        }
+
+        comp
+    }
 }
 
 impl ComponentManager for NameComponentManager {
     type Data = NameComponent;
 
-    fn iter(&self) -> Iter<NameComponent> {
-        self.components.iter()
+    fn iter(&self) -> ComponentIter<'_, NameComponent> {
+        Box::new(self.components.iter())
     }
 
-    fn iter_mut(&mut self) -> IterMut<NameComponent> {
-        self.components.iter_mut()
+    fn iter_mut(&mut self) -> ComponentIterMut<'_, NameComponent> {
+        self.dirty = true;
+        Box::new(self.components.iter_mut())
+    }
+
+    fn as_slice(&self) -> Option<&[NameComponent]> {
+        Some(&self.components)
+    }
+
+    fn as_mut_slice(&mut self) -> Option<&mut [NameComponent]> {
+        self.dirty = true;
+        Some(&mut self.components)
     }
 
     fn fetch(&self, owner: GenerationalId) -> Option<&NameComponent> {
@@ -90,6 +218,7 @@ impl ComponentManager for NameComponentManager {
 
     fn fetch_mut(&mut self, owner: GenerationalId) -> Option<&mut NameComponent> {
         if self.indir_map.contains_key(&owner) {
+            self.dirty = true;
             return Some(&mut self.components[self.indir_map[&owner]]);
         } else {
             None
@@ -105,6 +234,17 @@ impl ComponentManager for NameComponentManager {
             return Err(format!("Cannot attach multiple of the same component to Entity {}", owner.id));
         }
 
+        //Names changed since the last update must be indexed before they can be checked against.
+        if self.unique && self.dirty {
+            self.reindex();
+        }
+
+        if self.unique && self.name_taken(&value.name) {
+            return Err(format!("Cannot attach a second NameComponent named {}", value.name));
+        }
+
+        self.name_index.entry(value.name.clone()).or_default().push(owner);
+
         let ind = self.components.len();
         self.components.push(value);
 
@@ -113,9 +253,24 @@ impl ComponentManager for NameComponentManager {
         Ok(())
     }
 
+    fn check_insert(&self, values: &[&NameComponent]) -> Result<(), String> {
+        if !self.unique {
+            return Ok(());
+        }
+
+        for (i, v) in values.iter().enumerate() {
+            if self.name_taken(&v.name) || values[..i].iter().any(|o| o.name == v.name) {
+                return Err(format!("Cannot attach a second NameComponent named {}", v.name));
+            }
+        }
+
+        Ok(())
+    }
+
     fn delete(&mut self, owner: GenerationalId) -> Result<(), String> {
         if self.indir_map.contains_key(&owner) {
-            self.to_delete.push(self.indir_map[&owner] as usize);
+            //The name stays indexed until update actually removes the Component.
+            self.to_delete.push(self.indir_map[&owner]);
             Ok(())
         } else {
             Err(format!("Entity {} does not have a NameComponent", owner.id))
@@ -123,57 +278,182 @@ impl ComponentManager for NameComponentManager {
     }
 
     fn delete_now(&mut self, owner: GenerationalId) -> Result<(), String> {
-        if self.indir_map.contains_key(&owner) {
-            self.to_delete.sort_unstable();
-
-            let d = self.indir_map[&owner] as usize;
-
-            if self.to_delete.len() > 0 {
-                let mut i = 0;
-                let mut j = self.to_delete.len() - 1;
-                let mut m = (j - i) / 2;
-
-                while i <= j {
-                    if self.to_delete[m] > d {
-                        j = m;
-                    } else if self.to_delete[m] < d {
-                        i = m;
-                    } else {
-                        break;
-                    }
-                    m = (j - i) / 2;
-                }
-
-                if self.to_delete[m] == d {
-                    self.to_delete.remove(m);
-                    for i in m..self.to_delete.len() {
-                        self.to_delete[i] -= 1;
-                    }
-                }
-            } else {
-                let _ = self.to_delete.pop();
-            }
-
-            self.components.remove(self.indir_map[&owner]);
-            self.indir_map.remove(&owner);
+        self.take(owner).map(|_| ())
+    }
 
-            Ok(())
+    fn take(&mut self, owner: GenerationalId) -> Result<NameComponent, String> {
+        if self.indir_map.contains_key(&owner) {
+            let d = self.indir_map[&owner];
+            self.to_delete.retain(|i| *i != d);
 
+            Ok(self.remove_at(d))
         } else {
             Err(format!("Entity {} does not have a NameComponent", owner.id))
         }
     }
 
     fn update(&mut self) {
+        let mut to_delete = std::mem::take(&mut self.to_delete);
+        to_delete.sort_unstable();
+        to_delete.dedup();
 
-        self.to_delete.sort_unstable();
+        //Removing from the back keeps the remaining indices valid.
+        for d in to_delete.into_iter().rev() {
+            self.remove_at(d);
+        }
 
-        for i in 0..self.to_delete.len() {
-            self.to_delete[i] -= i;
-            self.indir_map.remove(&self.components[self.to_delete[i]].owner);
-            self.components.remove(self.to_delete[i]);
+        if self.dirty {
+            self.reindex();
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(i: u32) -> GenerationalId {
+        GenerationalId::new(i, 1)
+    }
+
+    fn named(owner: GenerationalId, name: &str) -> NameComponent {
+        let mut c = NameComponent::new(name.to_string());
+        c.set_owner(owner);
+        c
+    }
+
+    #[test]
+    fn unique_rename_is_reported_as_conflict() {
+        let mut m = NameComponentManager::new_unique();
+        m.insert(id(0), named(id(0), "A")).unwrap();
+        m.insert(id(1), named(id(1), "B")).unwrap();
+
+        m.fetch_mut(id(1)).unwrap().name = "A".to_string();
+        m.update();
+
+        assert_eq!(m.find_all("A"), &[id(0)]);
+        assert!(m.find_all("B").is_empty());
+        assert_eq!(m.conflicts(), &[id(1)]);
+    }
+
+    #[test]
+    fn unique_insert_sees_renames_before_update() {
+        let mut m = NameComponentManager::new_unique();
+        m.insert(id(0), named(id(0), "A")).unwrap();
+        m.insert(id(1), named(id(1), "B")).unwrap();
+
+        m.fetch_mut(id(1)).unwrap().name = "C".to_string();
+
+        assert!(m.insert(id(2), named(id(2), "B")).is_ok());
+        assert!(m.insert(id(3), named(id(3), "C")).is_err());
+        assert_eq!(m.find_all("B"), &[id(2)]);
+    }
+
+    #[test]
+    fn unique_insert_ignores_pending_deletions() {
+        let mut m = NameComponentManager::new_unique();
+        m.insert(id(0), named(id(0), "A")).unwrap();
+        m.insert(id(1), named(id(1), "B")).unwrap();
+        m.delete(id(0)).unwrap();
+
+        m.fetch_mut(id(1)).unwrap().name = "D".to_string();
+
+        assert!(m.insert(id(2), named(id(2), "A")).is_ok());
+        m.update();
+        assert_eq!(m.find_all("A"), &[id(2)]);
+        assert!(m.conflicts().is_empty());
+    }
+
+    #[test]
+    fn deleted_name_is_found_until_update() {
+        let mut m = NameComponentManager::new();
+        m.insert(id(0), named(id(0), "A")).unwrap();
+        m.delete(id(0)).unwrap();
+
+        assert_eq!(m.find("A"), Some(id(0)));
+        assert!(m.fetch(id(0)).is_some());
+
+        m.update();
+        assert_eq!(m.find("A"), None);
+        assert!(!m.has_component(id(0)));
+    }
+
+    #[test]
+    fn unique_name_is_free_once_deleted() {
+        let mut m = NameComponentManager::new_unique();
+        m.insert(id(0), named(id(0), "A")).unwrap();
+        m.delete(id(0)).unwrap();
+
+        assert!(m.check_insert(&[&named(id(1), "A")]).is_ok());
+        m.insert(id(1), named(id(1), "A")).unwrap();
+        assert!(m.insert(id(2), named(id(2), "A")).is_err());
+        assert_eq!(m.find_all("A"), &[id(0), id(1)]);
+
+        m.update();
+        assert_eq!(m.find_all("A"), &[id(1)]);
+    }
+
+    //Returns a NameComponentManager where slot i is named after i.
+    fn numbered(n: u32) -> NameComponentManager {
+        let mut m = NameComponentManager::new();
+        for i in 0..n {
+            m.insert(id(i), named(id(i), &i.to_string())).unwrap();
+        }
+        m
+    }
+
+    fn names(m: &NameComponentManager) -> Vec<String> {
+        m.iter().map(|n| n.name.clone()).collect()
+    }
+
+    #[test]
+    fn index_follows_swap_removal() {
+        let mut m = numbered(3);
+
+        m.delete_now(id(0)).unwrap();
+
+        assert_eq!(names(&m), vec!["2", "1"]);
+        assert!(m.find("0").is_none());
+        assert_eq!(m.find("2"), Some(id(2)));
+        assert_eq!(m.fetch(m.find("2").unwrap()).unwrap().name, "2");
+    }
+
+    #[test]
+    fn repeated_delete_removes_one_index() {
+        let mut m = numbered(3);
+
+        //Deletions are kept as indices, so a repeat must not remove whatever moves into the index.
+        m.delete(id(0)).unwrap();
+        m.delete(id(0)).unwrap();
+        m.update();
+
+        assert_eq!(names(&m), vec!["2", "1"]);
+        assert_eq!(m.find("1"), Some(id(1)));
+    }
+
+    #[test]
+    fn pending_delete_of_last_follows_swap() {
+        let mut m = numbered(3);
+
+        m.delete(id(2)).unwrap();
+        m.delete_now(id(0)).unwrap();
+        m.update();
+
+        assert_eq!(names(&m), vec!["1"]);
+        assert_eq!(m.fetch(id(1)).unwrap().name, "1");
+    }
+
+    #[test]
+    fn reused_name_points_at_new_generation() {
+        let mut m = numbered(2);
+        let new_id = GenerationalId::new(0, 2);
+
+        m.delete(id(0)).unwrap();
+        m.insert(new_id, named(new_id, "0")).unwrap();
+        m.update();
 
-        self.to_delete.clear();
+        assert!(m.fetch(id(0)).is_none());
+        assert_eq!(m.find_all("0"), &[new_id]);
+        assert_eq!(m.fetch(id(1)).unwrap().name, "1");
     }
 }
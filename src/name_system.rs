@@ -1,7 +1,6 @@
 use crate::system::system::*;
 use crate::world::world::*;
 use crate::name_component::*;
-use crate::component::component_manager::*;
 use crate::query::query::*;
 
 pub struct NameSystem {
@@ -22,11 +21,8 @@ impl System for NameSystem {
     }
 
     fn run(&mut self, w: &World, _dt: f32) {
-        let names = w.manager::<NameComponent>().unwrap();
-        let names = downcast_read_lock::<NameComponent>(&names);
-
-        for n in names.iter() {
+        w.for_each::<NameComponent, _>(|n| {
             println!("{}", n.name);
-        }
+        });
     }
 }
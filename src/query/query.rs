@@ -1,5 +1,16 @@
 use std::any::*;
 use crate::component::component::*;
+use crate::world::resource::*;
+
+///The trait for any type whose presence on an Entity can be checked by a Query: every Component,
+///and every Tag, which has to opt in alongside its Tag impl with
+///
+///    impl Tag for Enemy {}
+///    impl QueryTarget for Enemy {}
+///
+pub trait QueryTarget: Send + Sync + 'static {}
+
+impl<T: Component> QueryTarget for T {}
 
 ///Defines the type of access for a given QueryElement.
 #[derive(Copy, Clone, Debug)]
@@ -17,13 +28,29 @@ pub struct QueryPart {
 }
 
 impl QueryPart {
-    ///Constructs a new QueryPart using the TypeId of T
+    ///Constructs a new QueryPart using the TypeId of the Component T.
     pub fn new<T: Component>(a: QueryAccess) -> QueryPart {
         QueryPart {
             acc: a,
             comp: TypeId::of::<T>()
         }
     }
+
+    ///Constructs a new QueryPart using the TypeId of the Component or Tag T.
+    pub fn target<T: QueryTarget>(a: QueryAccess) -> QueryPart {
+        QueryPart {
+            acc: a,
+            comp: TypeId::of::<T>()
+        }
+    }
+
+    ///Constructs a new QueryPart using the TypeId of the Resource R
+    pub fn resource<R: Resource>(a: QueryAccess) -> QueryPart {
+        QueryPart {
+            acc: a,
+            comp: TypeId::of::<R>()
+        }
+    }
 }
 
 ///A full query element describing the conditions of a section of a query (can be a deep recrusion,
@@ -41,9 +68,9 @@ pub enum QueryElement {
 #[allow(dead_code)]
 impl QueryElement {
 
-    ///Returns a Part with QueryAccess::Check.
-    pub fn has<T: Component>() -> QueryElement {
-        QueryElement::Part(QueryPart::new::<T>(QueryAccess::Check))
+    ///Returns a Part with QueryAccess::Check, for either a Component or a Tag.
+    pub fn has<T: QueryTarget>() -> QueryElement {
+        QueryElement::Part(QueryPart::target::<T>(QueryAccess::Check))
     }
 
     ///Returns a Part with QueryAccess::Read.
@@ -79,10 +106,14 @@ impl QueryElement {
 }
 
 ///Represents a full Query to a World for all Entities whose Components fulfill the conditions of the Query.
-///Implicitly an And of all QueryElements added to it.
+///Implicitly an And of all QueryElements added to it. Also records which Resources are accessed,
+///so conflicts on them can be found alongside conflicts on Components. Disabled Entities never
+///fulfill a Query unless it explicitly includes them.
 #[derive(Clone, Debug)]
 pub struct Query {
-    pub query: Vec<QueryElement>
+    pub query: Vec<QueryElement>,
+    pub resources: Vec<QueryPart>,
+    pub include_disabled: bool
 }
 
 #[allow(dead_code)]
@@ -93,7 +124,9 @@ impl Query {
         let mut v = Vec::new();
         v.push(qe);
         Query {
-            query: v
+            query: v,
+            resources: Vec::new(),
+            include_disabled: false
         }
     }
 
@@ -103,4 +136,44 @@ impl Query {
         self
     }
 
+    ///Makes disabled Entities able to fulfill the Query.
+    pub fn include_disabled(&mut self) -> &mut Query {
+        self.include_disabled = true;
+        self
+    }
+
+    ///Declares that the Resource R is read.
+    pub fn read_resource<R: Resource>(&mut self) -> &mut Query {
+        self.resources.push(QueryPart::resource::<R>(QueryAccess::Read));
+        self
+    }
+
+    ///Declares that the Resource R is read and written.
+    pub fn read_write_resource<R: Resource>(&mut self) -> &mut Query {
+        self.resources.push(QueryPart::resource::<R>(QueryAccess::ReadWrite));
+        self
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::test_components::*;
+
+    struct Score;
+    struct Clock;
+
+    #[test]
+    fn resource_access_is_recorded_apart_from_components() {
+        let mut q = Query::new(QueryElement::read::<Position>());
+        q.read_resource::<Score>().read_write_resource::<Clock>();
+
+        assert_eq!(q.query.len(), 1);
+        assert_eq!(q.resources.len(), 2);
+        assert_eq!(q.resources[0].comp, TypeId::of::<Score>());
+        assert!(matches!(q.resources[0].acc, QueryAccess::Read));
+        assert_eq!(q.resources[1].comp, TypeId::of::<Clock>());
+        assert!(matches!(q.resources[1].acc, QueryAccess::ReadWrite));
+    }
 }
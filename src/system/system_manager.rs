@@ -1,6 +1,7 @@
 use std::any::*;
 use crate::world::world::*;
 use crate::system::system::*;
+use crate::component::tracked_manager::*;
 
 use std::vec::Vec;
 use std::collections::{HashMap};
@@ -26,6 +27,7 @@ pub struct NodeHandle<'a> {
 pub struct SystemManager {
     systems: Vec<Box<dyn System>>,
     enabled: Vec<bool>,
+    last_run: Vec<Option<u32>>,
 
     sys_map: HashMap<TypeId, usize>,
 
@@ -46,6 +48,7 @@ impl SystemManager {
         SystemManager {
             systems: sys_vec,
             enabled: enabled,
+            last_run: Vec::new(),
             sys_map: map
         }
     }
@@ -55,6 +58,7 @@ impl SystemManager {
         self.sys_map.insert(TypeId::of::<S>(), self.systems.len());
         self.systems.push(Box::new(sys));
         self.enabled.push(true);
+        self.last_run.push(None);
     }
 
     ///Marks a given System as disabled.
@@ -71,12 +75,146 @@ impl SystemManager {
         }
     }
 
-    ///Dispatches all Systems in the order they were inserted, skipping disabled Systems.
-    pub fn execute(&mut self, world: &World, dt: f32) {
+    ///Dispatches all Systems in the order they were inserted, skipping disabled Systems, then
+    ///applies any Commands they recorded. Returns the errors of the Commands that failed.
+    ///
+    ///The World's change tick advances before each System runs and once more after the last, so
+    ///each System sees exactly the changes made since its own previous run through World::changed.
+    ///A System that has not run before sees every Component as added.
+    pub fn execute(&mut self, world: &mut World, dt: f32) -> Result<(), String> {
+        //Disabled Systems keep their last run, which must not fall too far behind to compare.
+        let now = world.change_tick();
+        for t in self.last_run.iter_mut().flatten() {
+            *t = clamp_tick(*t, now);
+        }
+
         for i in 0..self.systems.len() {
             if self.enabled[i] {
+                let tick = world.increment_change_tick();
+                world.set_last_run_tick(self.last_run[i].unwrap_or(tick.wrapping_sub(MAX_TICK_AGE)));
+
                 self.systems[i].run(world, dt);
+
+                self.last_run[i] = Some(tick);
             }
         }
+
+        let now = world.increment_change_tick();
+        world.set_last_run_tick(now.wrapping_sub(MAX_TICK_AGE));
+
+        world.apply_commands()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::query::*;
+    use crate::component::vec_storage::*;
+    use crate::component::test_components::*;
+    use crate::common::generational_id::*;
+    use std::sync::{Arc, Mutex};
+
+    #[allow(dead_code)]
+    struct Spawner {
+        query: Query
+    }
+
+    impl System for Spawner {
+        fn query(&self) -> &Query {
+            &self.query
+        }
+
+        fn run(&mut self, w: &World, _dt: f32) {
+            let mut cmd = w.commands();
+            let id = cmd.spawn();
+            cmd.attach(id, Position::new(id, 0));
+            cmd.delete(GenerationalId::new(99, 1));
+        }
+    }
+
+    #[test]
+    fn execute_applies_commands_and_returns_failures() {
+        let mut w = World::new();
+        w.register_manager(VecStorage::<Position>::new());
+
+        let mut sys = SystemManager::new();
+        sys.append(Spawner { query: Query::new(QueryElement::read_write::<Position>()) });
+
+        assert!(sys.execute(&mut w, 0.0).is_err());
+        assert_eq!(w.entity_count(), 1);
+
+        let mut n = 0;
+        w.for_each::<Position, _>(|_| n += 1);
+        assert_eq!(n, 1);
+    }
+
+    //Moves every Position, marking all of them as changed.
+    #[allow(dead_code)]
+    struct Mover {
+        query: Query
+    }
+
+    impl System for Mover {
+        fn query(&self) -> &Query {
+            &self.query
+        }
+
+        fn run(&mut self, w: &World, _dt: f32) {
+            w.for_each_mut::<Position, _>(|p| p.x += 1);
+        }
+    }
+
+    type Seen = Arc<Mutex<Vec<(usize, usize)>>>;
+
+    //Records how many Positions were added and changed since its last run.
+    #[allow(dead_code)]
+    struct Watcher {
+        query: Query,
+        seen: Seen
+    }
+
+    impl System for Watcher {
+        fn query(&self) -> &Query {
+            &self.query
+        }
+
+        fn run(&mut self, w: &World, _dt: f32) {
+            self.seen.lock().unwrap().push((w.added::<Position>().unwrap().len(), w.changed::<Position>().unwrap().len()));
+        }
+    }
+
+    fn watched() -> (World, SystemManager, Seen) {
+        let mut w = World::new();
+        w.register_manager(VecStorage::<Position>::new());
+        for _ in 0..3 {
+            let id = w.spawn();
+            w.attach_component(id, Position::new(id, 0)).unwrap();
+        }
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut sys = SystemManager::new();
+        sys.append(Mover { query: Query::new(QueryElement::read_write::<Position>()) });
+        sys.append(Watcher { query: Query::new(QueryElement::read::<Position>()), seen: seen.clone() });
+        sys.disable::<Mover>();
+
+        (w, sys, seen)
+    }
+
+    #[test]
+    fn systems_see_changes_since_their_own_last_run() {
+        let (mut w, mut sys, seen) = watched();
+
+        sys.execute(&mut w, 0.0).unwrap();
+        sys.execute(&mut w, 0.0).unwrap();
+
+        let id = w.spawn();
+        w.attach_component(id, Position::new(id, 0)).unwrap();
+        sys.execute(&mut w, 0.0).unwrap();
+
+        sys.enable::<Mover>();
+        sys.execute(&mut w, 0.0).unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), vec![(3, 3), (0, 0), (1, 1), (0, 4)]);
     }
 }
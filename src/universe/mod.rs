@@ -1 +1,4 @@
 pub mod universe;
+pub mod run_config;
+pub mod scene;
+pub mod plugin;
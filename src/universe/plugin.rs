@@ -0,0 +1,26 @@
+use crate::universe::universe::*;
+
+use downcast_rs::*;
+use std::vec::Vec;
+
+///A reusable feature, such as physics, audio or UI, that sets itself up in a Universe.
+///
+///A Plugin registers everything its feature needs, e.g. ComponentManagers, Systems, Resources
+///and Events, on the top Scene of the Universe in build. Each Plugin type is only built once per
+///Scene, and the Plugins it depends on are built before it.
+///
+pub trait Plugin: DowncastSync {
+    ///Registers the Plugin's ComponentManagers, Systems, Resources and Events to the Universe.
+    fn build(&self, universe: &mut Universe);
+
+    ///Returns the Plugins that must be added before this one. None by default.
+    fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+        Vec::new()
+    }
+
+    ///Returns the name of the Plugin, for error messages.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+impl_downcast!(sync Plugin);
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+///Settings for Universe::run.
+///
+///Every step advances the game by exactly timestep seconds. When a frame falls behind, up to
+///max_steps_per_frame steps are run to catch up, and any time beyond that is dropped so a slow
+///frame cannot snowball into ever slower ones. Headless runs can be bounded by max_frames or
+///max_duration; otherwise the loop runs until World::request_quit is called.
+///
+#[derive(Copy, Clone, Debug)]
+pub struct RunConfig {
+    pub timestep: f32,
+    pub max_steps_per_frame: u32,
+    pub max_frames: Option<u64>,
+    pub max_duration: Option<Duration>
+}
+
+#[allow(dead_code)]
+impl RunConfig {
+    ///Creates a new RunConfig with the given timestep in seconds, catching up at most 5 steps per
+    ///frame and with no frame or duration limit.
+    pub fn new(timestep: f32) -> RunConfig {
+        RunConfig {
+            timestep,
+            max_steps_per_frame: 5,
+            max_frames: None,
+            max_duration: None
+        }
+    }
+
+    ///Checks that the game can advance under this RunConfig, and returns its timestep as a Duration.
+    ///The timestep must be a positive, finite number of seconds, and at least one step must be
+    ///allowed per frame.
+    pub fn check(&self) -> Result<Duration, String> {
+        if self.max_steps_per_frame == 0 {
+            return Err("RunConfig must allow at least one step per frame".to_string());
+        }
+
+        match Duration::try_from_secs_f32(self.timestep) {
+            Ok(d) if !d.is_zero() => Ok(d),
+            _ => Err(format!("RunConfig timestep must be a positive number of seconds, not {}", self.timestep))
+        }
+    }
+}
+
+///The reason Universe::run stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QuitReason {
+    ///World::request_quit was called with this reason.
+    Requested(String),
+    ///RunConfig::max_frames frames were run.
+    MaxFrames,
+    ///RunConfig::max_duration elapsed.
+    MaxDuration,
+    ///Every Scene was popped off of the Scene stack.
+    NoScenes
+}
+
+///Statistics over the time spent running each frame, excluding time spent waiting for the next
+///step to be due.
+#[derive(Copy, Clone, Debug)]
+#[allow(dead_code)]
+pub struct FrameStats {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration
+}
+
+///Summary of a finished Universe::run.
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct RunReport {
+    pub reason: QuitReason,
+    pub frames: u64,
+    pub steps: u64,
+    pub elapsed: Duration,
+    ///The errors of the Commands that failed during each step, in the order they occurred.
+    pub errors: Vec<String>,
+    pub frame_time: FrameStats
+}
@@ -0,0 +1,53 @@
+use crate::world::world::*;
+use crate::system::system_manager::*;
+
+use std::any::TypeId;
+use std::collections::HashSet;
+
+///Defines what a Scene does while another Scene is on top of it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum CoveredBehavior {
+    ///The Scene stops running until it is on top again.
+    Pause,
+    ///The Scene keeps running underneath the Scenes on top of it.
+    Tick
+}
+
+///A World together with the Systems that run on it, such as a menu, a level or a mini-game.
+pub struct Scene {
+    pub world: World,
+    pub systems: SystemManager,
+    pub covered: CoveredBehavior,
+    pub plugins: HashSet<TypeId>
+}
+
+impl Scene {
+    ///Creates a new Scene from a World, its SystemManager, and what it does while covered.
+    pub fn new(world: World, systems: SystemManager, covered: CoveredBehavior) -> Scene {
+        Scene {
+            world,
+            systems,
+            covered,
+            plugins: HashSet::new()
+        }
+    }
+
+    ///Runs the Scene's Systems for one iteration and updates its World. Returns the errors of any
+    ///Commands that failed along the way.
+    pub fn step(&mut self, dt: f32) -> Result<(), String> {
+        let executed = self.systems.execute(&mut self.world, dt);
+        let updated = self.world.update();
+        match (executed, updated) {
+            (Err(a), Err(b)) => Err(format!("{}\n{}", a, b)),
+            (a, b) => a.and(b)
+        }
+    }
+}
+
+///A change to the Scene stack, waiting to be applied between frames.
+pub enum SceneOp {
+    Push(Scene),
+    Pop,
+    Switch(Scene)
+}
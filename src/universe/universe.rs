@@ -1,16 +1,27 @@
 use crate::world::world::*;
 use crate::system::system_manager::*;
+use crate::universe::run_config::*;
+use crate::universe::scene::*;
+use crate::universe::plugin::*;
 
-///Stores a World and SystemManager.
+use std::any::TypeId;
+
+use std::time::{Duration, Instant};
+
+///Stores a stack of Scenes, each a World and SystemManager.
 ///
-///Stores a World and SystemManager and coordinates access to each from the rest of the main program.
+///Stores a stack of Scenes and coordinates access to each from the rest of the main program. The
+///Scene on top of the stack is the active one, and Scenes below it either pause or keep running
+///depending on their CoveredBehavior. Changes to the stack are deferred until the start of the
+///next frame.
 ///The initialization function should be responsible for setting up any libraries that need to be
 ///initialized, such as SDL2 or OpenGl, as well as setting the initial state of the World and
-///SystemManager.
+///SystemManager of the first Scene.
 #[allow(dead_code)]
 pub struct Universe {
-    world: World,
-    systems: SystemManager
+    scenes: Vec<Scene>,
+    pending: Vec<SceneOp>,
+    building: Vec<TypeId>
 }
 
 #[allow(dead_code)]
@@ -24,23 +35,482 @@ impl Universe {
         let (w, sm) = init_func();
 
         Universe {
-            world: w,
-            systems: sm
+            scenes: vec![Scene::new(w, sm, CoveredBehavior::Pause)],
+            pending: Vec::new(),
+            building: Vec::new()
         }
     }
 
-    ///Gets a mutable reference to the held World.
+    ///Gets a mutable reference to the World of the top Scene. Panics if the Scene stack is empty.
     pub fn world_mut(&mut self) -> &mut World {
-        &mut self.world
+        &mut self.scenes.last_mut().expect("The Scene stack is empty").world
     }
 
-    ///Gets a mutable reference to the held SystemManager.
+    ///Gets a mutable reference to the SystemManager of the top Scene. Panics if the Scene stack is
+    ///empty.
     pub fn systems_mut(&mut self) -> &mut SystemManager {
-        &mut self.systems
+        &mut self.scenes.last_mut().expect("The Scene stack is empty").systems
+    }
+
+    ///Builds a Plugin into the top Scene, after first adding every Plugin it depends on. A Plugin
+    ///that was already added to the top Scene is ignored. Fails if Plugins depend on each other in
+    ///a cycle, or if the Scene stack is empty.
+    pub fn add_plugin<P: Plugin>(&mut self, plugin: P) -> Result<(), String> {
+        self.add_boxed_plugin(Box::new(plugin))
+    }
+
+    ///Builds a Plugin given as a trait object into the top Scene, like add_plugin.
+    pub fn add_boxed_plugin(&mut self, plugin: Box<dyn Plugin>) -> Result<(), String> {
+        let t = (*plugin).as_any().type_id();
+
+        match self.scenes.last() {
+            Some(s) if s.plugins.contains(&t) => return Ok(()),
+            Some(_) => {},
+            None => return Err(format!("Cannot add Plugin {} with no Scene", plugin.name()))
+        }
+
+        if self.building.contains(&t) {
+            return Err(format!("Plugin {} depends on itself", plugin.name()));
+        }
+
+        self.building.push(t);
+        let res = plugin.dependencies().into_iter().try_for_each(|d| self.add_boxed_plugin(d));
+        if res.is_ok() {
+            plugin.build(self);
+        }
+        self.building.pop();
+
+        if res.is_ok() {
+            if let Some(s) = self.scenes.last_mut() {
+                s.plugins.insert(t);
+            }
+        }
+
+        res
+    }
+
+    ///Returns the number of Scenes on the stack.
+    pub fn scene_count(&self) -> usize {
+        self.scenes.len()
+    }
+
+    ///Pushes a Scene on top of the stack at the start of the next frame.
+    pub fn push_scene(&mut self, scene: Scene) {
+        self.pending.push(SceneOp::Push(scene));
+    }
+
+    ///Pops the top Scene off of the stack at the start of the next frame.
+    pub fn pop_scene(&mut self) {
+        self.pending.push(SceneOp::Pop);
+    }
+
+    ///Replaces the top Scene with another at the start of the next frame.
+    pub fn switch_scene(&mut self, scene: Scene) {
+        self.pending.push(SceneOp::Switch(scene));
+    }
+
+    ///Applies every pending change to the Scene stack, in the order they were requested.
+    pub fn apply_scene_ops(&mut self) {
+        for op in std::mem::take(&mut self.pending) {
+            match op {
+                SceneOp::Push(s) => self.scenes.push(s),
+                SceneOp::Pop => {
+                    self.scenes.pop();
+                },
+                SceneOp::Switch(s) => {
+                    self.scenes.pop();
+                    self.scenes.push(s);
+                }
+            }
+        }
+    }
+
+    ///Applies pending Scene changes, then runs the top Scene and every covered Scene that keeps
+    ///ticking for one iteration, from the bottom of the stack up, updating each of their Worlds.
+    ///Every Scene is stepped even if an earlier one fails. Returns the errors of any Commands that
+    ///failed.
+    pub fn run_once(&mut self, dt: f32) -> Result<(), String> {
+        self.apply_scene_ops();
+
+        let mut errors = Vec::new();
+
+        let top = self.scenes.len().saturating_sub(1);
+        for (i, s) in self.scenes.iter_mut().enumerate() {
+            if i == top || s.covered == CoveredBehavior::Tick {
+                if let Err(e) = s.step(dt) {
+                    errors.push(e);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    //Returns the first quit reason requested by the World of any Scene.
+    fn quit_reason(&self) -> Option<String> {
+        self.scenes.iter().find_map(|s| s.world.quit_reason())
+    }
+
+    ///Runs the Scenes in fixed timesteps until the World of any Scene requests to quit, the Scene
+    ///stack is empty, or a limit set in config is reached. Every request to quit is cleared before
+    ///returning, so the Universe can be run again. Fails without running anything if
+    ///RunConfig::check rejects config.
+    pub fn run(&mut self, config: RunConfig) -> Result<RunReport, String> {
+        let step = config.check()?;
+        let start = Instant::now();
+        let mut last = start;
+        let mut acc = Duration::from_secs(0);
+
+        let mut frames: u64 = 0;
+        let mut steps: u64 = 0;
+        let mut min = Duration::MAX;
+        let mut max = Duration::from_secs(0);
+        let mut total = Duration::from_secs(0);
+        let mut errors = Vec::new();
+
+        let reason = loop {
+            if let Some(r) = self.quit_reason() {
+                break QuitReason::Requested(r);
+            }
+            if self.scenes.is_empty() && self.pending.is_empty() {
+                break QuitReason::NoScenes;
+            }
+            if config.max_frames.is_some_and(|m| frames >= m) {
+                break QuitReason::MaxFrames;
+            }
+            if config.max_duration.is_some_and(|m| start.elapsed() >= m) {
+                break QuitReason::MaxDuration;
+            }
+
+            if acc < step {
+                std::thread::sleep(step - acc);
+            }
+
+            let frame_start = Instant::now();
+            acc += frame_start - last;
+            last = frame_start;
+
+            let mut n = 0;
+            while acc >= step && n < config.max_steps_per_frame && self.quit_reason().is_none() {
+                if let Err(e) = self.run_once(config.timestep) {
+                    errors.push(e);
+                }
+                acc -= step;
+                n += 1;
+            }
+
+            //Whatever could not be caught up on is dropped rather than carried into the next frame.
+            if acc >= step {
+                acc = Duration::from_secs(0);
+            }
+
+            let t = frame_start.elapsed();
+            min = min.min(t);
+            max = max.max(t);
+            total += t;
+            frames += 1;
+            steps += n as u64;
+        };
+
+        for s in self.scenes.iter() {
+            s.world.clear_quit();
+        }
+
+        Ok(RunReport {
+            reason,
+            frames,
+            steps,
+            elapsed: start.elapsed(),
+            errors,
+            frame_time: FrameStats {
+                min: if frames > 0 { min } else { Duration::from_secs(0) },
+                max,
+                mean: if frames > 0 { total.div_f64(frames as f64) } else { Duration::from_secs(0) }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::system::*;
+    use crate::query::query::*;
+    use crate::common::generational_id::*;
+    use crate::name_component::NameComponent;
+
+    //Counts its runs, requesting to quit after the given number and recording a failing Command
+    //on every run.
+    #[allow(dead_code)]
+    struct Counter {
+        query: Query,
+        runs: u32,
+        quit_after: u32
+    }
+
+    impl System for Counter {
+        fn query(&self) -> &Query {
+            &self.query
+        }
+
+        fn run(&mut self, w: &World, _dt: f32) {
+            self.runs += 1;
+            w.commands().delete(GenerationalId::new(99, 1));
+            if self.runs == self.quit_after {
+                w.request_quit("done");
+            }
+        }
+    }
+
+    fn universe(quit_after: u32) -> Universe {
+        Universe::new(|| {
+            let mut sm = SystemManager::new();
+            sm.append(Counter { query: Query::new(QueryElement::read::<NameComponent>()), runs: 0, quit_after });
+            (World::new(), sm)
+        })
+    }
+
+    fn fast() -> RunConfig {
+        RunConfig::new(0.001)
+    }
+
+    //The name of a Scene and the number of times its Ticker has run.
+    struct Label(&'static str);
+    struct Ticks(u32);
+
+    #[allow(dead_code)]
+    struct Ticker {
+        query: Query
     }
 
-    ///Runs the SystemManager for one iteration.
-    pub fn run_once(&mut self, dt: f32) {
-        self.systems.execute(&mut self.world, dt);
+    impl System for Ticker {
+        fn query(&self) -> &Query {
+            &self.query
+        }
+
+        fn run(&mut self, w: &World, _dt: f32) {
+            w.resource_mut::<Ticks>().unwrap().0 += 1;
+        }
+    }
+
+    fn scene(label: &'static str, covered: CoveredBehavior) -> Scene {
+        let mut w = World::new();
+        w.insert_resource(Label(label));
+        w.insert_resource(Ticks(0));
+
+        let mut sm = SystemManager::new();
+        sm.append(Ticker { query: Query::new(QueryElement::read::<NameComponent>()) });
+        Scene::new(w, sm, covered)
+    }
+
+    fn labels(u: &Universe) -> Vec<&'static str> {
+        u.scenes.iter().filter_map(|s| s.world.resource::<Label>().map(|l| l.0)).collect()
+    }
+
+    fn ticks(u: &Universe) -> Vec<u32> {
+        u.scenes.iter().filter_map(|s| s.world.resource::<Ticks>().map(|t| t.0)).collect()
+    }
+
+    #[test]
+    fn scene_ops_wait_for_the_next_frame_and_apply_in_order() {
+        let mut u = universe(0);
+
+        u.push_scene(scene("a", CoveredBehavior::Pause));
+        u.push_scene(scene("b", CoveredBehavior::Pause));
+        u.switch_scene(scene("c", CoveredBehavior::Pause));
+        assert_eq!(u.scene_count(), 1);
+
+        u.run_once(0.1).unwrap();
+        assert_eq!(labels(&u), vec!["a", "c"]);
+        assert_eq!(ticks(&u), vec![0, 1]);
+
+        u.pop_scene();
+        u.switch_scene(scene("d", CoveredBehavior::Pause));
+        u.push_scene(scene("e", CoveredBehavior::Pause));
+        u.run_once(0.1).unwrap();
+        assert_eq!(labels(&u), vec!["d", "e"]);
+        assert_eq!(u.scene_count(), 3);
+    }
+
+    #[test]
+    fn covered_scenes_pause_or_tick() {
+        let mut u = universe(0);
+        u.push_scene(scene("ticking", CoveredBehavior::Tick));
+        u.push_scene(scene("paused", CoveredBehavior::Pause));
+        u.push_scene(scene("top", CoveredBehavior::Pause));
+
+        //The covered bottom Scene pauses, so its failing Command is never recorded.
+        u.run_once(0.1).unwrap();
+        u.run_once(0.1).unwrap();
+        assert_eq!(ticks(&u), vec![2, 0, 2]);
+
+        u.pop_scene();
+        u.run_once(0.1).unwrap();
+        assert_eq!(labels(&u), vec!["ticking", "paused"]);
+        assert_eq!(ticks(&u), vec![3, 1]);
+    }
+
+    #[test]
+    fn run_rejects_configs_that_cannot_advance() {
+        let mut u = universe(1);
+
+        for timestep in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(u.run(RunConfig::new(timestep)).is_err());
+        }
+
+        let mut config = fast();
+        config.max_steps_per_frame = 0;
+        assert!(u.run(config).is_err());
+
+        assert_eq!(u.world_mut().quit_reason(), None);
+    }
+
+    #[test]
+    fn run_stops_at_requested_quit_and_collects_errors() {
+        let mut u = universe(3);
+
+        let report = u.run(fast()).unwrap();
+
+        assert_eq!(report.reason, QuitReason::Requested("done".to_string()));
+        assert_eq!(report.steps, 3);
+        assert_eq!(report.errors.len(), 3);
+        assert!(report.frames >= 1 && report.frames <= 3);
+        assert!(report.frame_time.min <= report.frame_time.mean && report.frame_time.mean <= report.frame_time.max);
+    }
+
+    #[test]
+    fn run_can_start_again_after_a_requested_quit() {
+        let mut u = universe(1);
+        assert_eq!(u.run(fast()).unwrap().reason, QuitReason::Requested("done".to_string()));
+        assert_eq!(u.world_mut().quit_reason(), None);
+
+        let mut config = fast();
+        config.max_frames = Some(2);
+        let report = u.run(config).unwrap();
+
+        assert_eq!(report.reason, QuitReason::MaxFrames);
+        assert!(report.steps >= 2);
+    }
+
+    #[test]
+    fn run_stops_at_max_frames() {
+        let mut u = universe(0);
+        let mut config = fast();
+        config.max_frames = Some(4);
+
+        let report = u.run(config).unwrap();
+
+        assert_eq!(report.reason, QuitReason::MaxFrames);
+        assert_eq!(report.frames, 4);
+        assert!(report.steps >= 4 && report.steps <= 4 * config.max_steps_per_frame as u64);
+    }
+
+    #[test]
+    fn run_stops_with_no_scenes() {
+        let mut u = universe(0);
+        u.pop_scene();
+
+        let report = u.run(fast()).unwrap();
+
+        //The pop is only applied by the first step, after which nothing is left to run.
+        assert_eq!(report.reason, QuitReason::NoScenes);
+        assert_eq!(report.frames, 1);
+        assert_eq!(u.scene_count(), 0);
+    }
+
+    //The names of the Plugins built into a World, in the order they were built.
+    struct Built(Vec<&'static str>);
+
+    fn built(u: &mut Universe) -> Vec<&'static str> {
+        u.world_mut().resource::<Built>().map(|b| b.0.clone()).unwrap_or_default()
+    }
+
+    fn record(u: &mut Universe, name: &'static str) {
+        let w = u.world_mut();
+        if w.resource::<Built>().is_none() {
+            w.insert_resource(Built(Vec::new()));
+        }
+        w.resource_mut::<Built>().unwrap().0.push(name);
+    }
+
+    struct Physics;
+    struct Audio;
+    struct Game;
+
+    impl Plugin for Physics {
+        fn build(&self, u: &mut Universe) {
+            record(u, "physics");
+        }
+    }
+
+    impl Plugin for Audio {
+        fn build(&self, u: &mut Universe) {
+            record(u, "audio");
+        }
+
+        fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+            vec![Box::new(Physics)]
+        }
+    }
+
+    impl Plugin for Game {
+        fn build(&self, u: &mut Universe) {
+            record(u, "game");
+        }
+
+        fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+            vec![Box::new(Physics), Box::new(Audio)]
+        }
+    }
+
+    #[test]
+    fn dependencies_are_built_first_and_once() {
+        let mut u = universe(0);
+
+        u.add_plugin(Game).unwrap();
+        assert_eq!(built(&mut u), vec!["physics", "audio", "game"]);
+
+        u.add_plugin(Game).unwrap();
+        u.add_plugin(Audio).unwrap();
+        assert_eq!(built(&mut u), vec!["physics", "audio", "game"]);
+    }
+
+    struct Left;
+    struct Right;
+
+    impl Plugin for Left {
+        fn build(&self, u: &mut Universe) {
+            record(u, "left");
+        }
+
+        fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+            vec![Box::new(Right)]
+        }
+    }
+
+    impl Plugin for Right {
+        fn build(&self, u: &mut Universe) {
+            record(u, "right");
+        }
+
+        fn dependencies(&self) -> Vec<Box<dyn Plugin>> {
+            vec![Box::new(Left)]
+        }
+    }
+
+    #[test]
+    fn dependency_cycle_is_an_error() {
+        let mut u = universe(0);
+
+        assert!(u.add_plugin(Left).is_err());
+        assert!(built(&mut u).is_empty());
+
+        //Nothing of the failed add is left behind to block Plugins added later.
+        u.add_plugin(Physics).unwrap();
+        assert_eq!(built(&mut u), vec!["physics"]);
     }
 }
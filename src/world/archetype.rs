@@ -0,0 +1,709 @@
+use crate::component::component::*;
+use crate::common::generational_id::*;
+
+use downcast_rs::*;
+use std::vec::Vec;
+use std::collections::HashMap;
+use std::any::{TypeId, type_name};
+
+///Defines where the World stores Components whose type has no registered ComponentManager.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum StorageMode {
+    ///Every Component type needs a registered ComponentManager.
+    Managers,
+    ///Component types without a registered ComponentManager are stored in Archetypes.
+    Archetypes
+}
+
+///A type-erased column of Components of a single type, stored contiguously.
+pub trait Column: DowncastSync {
+    ///Returns the name of the Component type stored in the column, for error messages.
+    fn type_name(&self) -> &'static str;
+
+    ///Returns the Component in the given row as a trait object.
+    fn get_dyn(&self, row: usize) -> &dyn Component;
+
+    ///Sets the owner of the Component in the given row.
+    fn set_owner(&mut self, row: usize, owner: GenerationalId);
+
+    ///Appends a Component given as a trait object. Fails if it is not of the column's type.
+    fn push_dyn(&mut self, value: Box<dyn Component>) -> Result<(), String>;
+
+    ///Removes the Component in the given row by swapping the last Component into its place, and
+    ///returns it as a trait object.
+    fn swap_remove_dyn(&mut self, row: usize) -> Box<dyn Component>;
+
+    ///Moves the Component in the given row onto the end of dest, which must be a column of the same
+    ///type, swapping the last Component into its place.
+    fn move_row(&mut self, row: usize, dest: &mut dyn Column);
+}
+impl_downcast!(sync Column);
+
+impl<T: Component> Column for Vec<T> {
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn get_dyn(&self, row: usize) -> &dyn Component {
+        &self[row]
+    }
+
+    fn set_owner(&mut self, row: usize, owner: GenerationalId) {
+        self[row].set_owner(owner);
+    }
+
+    fn push_dyn(&mut self, value: Box<dyn Component>) -> Result<(), String> {
+        match value.downcast::<T>() {
+            Ok(c) => {
+                self.push(*c);
+                Ok(())
+            },
+            Err(v) => Err(format!("Cannot insert a {} into a Column of {}", v.type_name(), type_name::<T>()))
+        }
+    }
+
+    fn swap_remove_dyn(&mut self, row: usize) -> Box<dyn Component> {
+        Box::new(self.swap_remove(row))
+    }
+
+    fn move_row(&mut self, row: usize, dest: &mut dyn Column) {
+        let c = self.swap_remove(row);
+        dest.downcast_mut::<Vec<T>>().unwrap().push(c);
+    }
+}
+
+///A table of every Entity with exactly the same set of archetype-stored Component types.
+///
+///Each Component type has its own contiguous column, and row i of every column belongs to the
+///i-th Entity, so iterating several Component types at once walks each column linearly.
+///
+pub struct Archetype {
+    types: Vec<TypeId>,
+    columns: Vec<Box<dyn Column>>,
+    entities: Vec<GenerationalId>,
+    add_edges: HashMap<TypeId, usize>,
+    remove_edges: HashMap<TypeId, Option<usize>>
+}
+
+#[allow(dead_code)]
+impl Archetype {
+
+    ///Returns the Component types stored in the Archetype, sorted by TypeId.
+    pub fn types(&self) -> &[TypeId] {
+        &self.types
+    }
+
+    ///Returns the Entities in the Archetype, in the same order as the rows of every column.
+    pub fn entities(&self) -> &[GenerationalId] {
+        &self.entities
+    }
+
+    ///Returns the number of Entities in the Archetype.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    ///Returns whether or not the Archetype has no Entities.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    ///Returns whether or not the Archetype stores Components of type t.
+    pub fn has_type(&self, t: TypeId) -> bool {
+        self.types.binary_search(&t).is_ok()
+    }
+
+    ///Returns the column of Components of type T, if the Archetype stores them.
+    pub fn column<T: Component>(&self) -> Option<&[T]> {
+        let i = self.types.binary_search(&TypeId::of::<T>()).ok()?;
+        self.columns[i].downcast_ref::<Vec<T>>().map(|v| v.as_slice())
+    }
+
+    ///Returns the column of Components of type T mutably, if the Archetype stores them.
+    pub fn column_mut<T: Component>(&mut self) -> Option<&mut [T]> {
+        let i = self.types.binary_search(&TypeId::of::<T>()).ok()?;
+        self.columns[i].downcast_mut::<Vec<T>>().map(|v| v.as_mut_slice())
+    }
+
+    ///Returns the columns of Components of types A and B mutably, if the Archetype stores both and
+    ///they are different types.
+    pub fn columns2_mut<A: Component, B: Component>(&mut self) -> Option<(&mut [A], &mut [B])> {
+        let i = self.types.binary_search(&TypeId::of::<A>()).ok()?;
+        let j = self.types.binary_search(&TypeId::of::<B>()).ok()?;
+        if i == j {
+            return None;
+        }
+
+        let (a, b) = pair_mut(&mut self.columns, i, j);
+        Some((a.downcast_mut::<Vec<A>>()?.as_mut_slice(), b.downcast_mut::<Vec<B>>()?.as_mut_slice()))
+    }
+
+    fn column_index(&self, t: TypeId) -> Option<usize> {
+        self.types.binary_search(&t).ok()
+    }
+}
+
+///Stores Components grouped into Archetypes by the set of Component types of their Entity.
+///
+///Attaching or detaching a Component moves the Entity, along with all of its other Components,
+///into the Archetype for its new set of types. The Archetype reached by adding or removing each
+///type is cached, so repeated moves do not need to look up the new set of types again.
+///Iterating with iter2 or iter2_mut only visits Archetypes holding both types, streaming through
+///their columns in order.
+///
+///Each Component type must be known before it can be stored, either by being attached with its
+///concrete type once, or by being registered with register.
+///
+pub struct ArchetypeStorage {
+    archetypes: Vec<Archetype>,
+    index: HashMap<Vec<TypeId>, usize>,
+    locations: Vec<Option<(usize, usize)>>,
+    factories: HashMap<TypeId, fn() -> Box<dyn Column>>
+}
+
+#[allow(dead_code)]
+impl ArchetypeStorage {
+
+    ///Creates a new ArchetypeStorage with no Archetypes.
+    pub fn new() -> ArchetypeStorage {
+        ArchetypeStorage {
+            archetypes: Vec::new(),
+            index: HashMap::new(),
+            locations: Vec::new(),
+            factories: HashMap::new()
+        }
+    }
+
+    ///Makes the Component type T storable, including through trait objects.
+    pub fn register<T: Component>(&mut self) {
+        self.factories.entry(TypeId::of::<T>()).or_insert(new_column::<T>);
+    }
+
+    ///Returns whether or not Components of type t can be stored.
+    pub fn accepts(&self, t: TypeId) -> bool {
+        self.factories.contains_key(&t)
+    }
+
+    ///Returns every Archetype, for iterating over their columns directly.
+    pub fn archetypes(&self) -> &[Archetype] {
+        &self.archetypes
+    }
+
+    ///Returns every Archetype mutably, for iterating over their columns directly.
+    pub fn archetypes_mut(&mut self) -> &mut [Archetype] {
+        &mut self.archetypes
+    }
+
+    ///Returns the Archetype and row of the given Entity, if it has any stored Components.
+    pub fn location(&self, id: GenerationalId) -> Option<(usize, usize)> {
+        match self.locations.get(id.id as usize) {
+            Some(Some((a, row))) if self.archetypes[*a].entities[*row] == id => Some((*a, *row)),
+            _ => None
+        }
+    }
+
+    ///Returns whether or not the given Entity has a stored Component of type t.
+    pub fn contains(&self, id: GenerationalId, t: TypeId) -> bool {
+        self.location(id).is_some_and(|(a, _)| self.archetypes[a].has_type(t))
+    }
+
+    ///Returns the type and type name of every stored Component of the given Entity.
+    pub fn types_of(&self, id: GenerationalId) -> Vec<(TypeId, &'static str)> {
+        match self.location(id) {
+            Some((a, _)) => {
+                let arch = &self.archetypes[a];
+                arch.types.iter().copied().zip(arch.columns.iter().map(|c| c.type_name())).collect()
+            },
+            None => Vec::new()
+        }
+    }
+
+    ///Returns the Component of type T of the given Entity, if it exists.
+    pub fn get<T: Component>(&self, id: GenerationalId) -> Option<&T> {
+        let (a, row) = self.location(id)?;
+        self.archetypes[a].column::<T>().map(|c| &c[row])
+    }
+
+    ///Returns the Component of type T of the given Entity mutably, if it exists.
+    pub fn get_mut<T: Component>(&mut self, id: GenerationalId) -> Option<&mut T> {
+        let (a, row) = self.location(id)?;
+        self.archetypes[a].column_mut::<T>().map(|c| &mut c[row])
+    }
+
+    ///Stores a Component of type T for the given Entity, moving it to its new Archetype. Fails if
+    ///the Entity already has one.
+    pub fn insert<T: Component>(&mut self, id: GenerationalId, value: T) -> Result<(), String> {
+        self.register::<T>();
+
+        let (a, col) = self.prepare_insert(id, TypeId::of::<T>())?;
+        self.archetypes[a].columns[col].downcast_mut::<Vec<T>>().unwrap().push(value);
+        Ok(())
+    }
+
+    ///Stores a Component given as a trait object for the given Entity, moving it to its new
+    ///Archetype. Fails if its type is unknown or the Entity already has one.
+    pub fn insert_dyn(&mut self, id: GenerationalId, value: Box<dyn Component>) -> Result<(), String> {
+        let t = (*value).as_any().type_id();
+        if !self.accepts(t) {
+            return Err(format!("No successfully registered ComponentManager for {}", value.type_name()));
+        }
+
+        let (a, col) = self.prepare_insert(id, t)?;
+        self.archetypes[a].columns[col].push_dyn(value)
+    }
+
+    ///Stores every Component given as trait objects for the given Entity, moving it straight to the
+    ///Archetype for its new set of types instead of through one Archetype per Component. Fails
+    ///without storing anything if a type is unknown, given twice, or already stored for the Entity.
+    pub fn insert_all_dyn(&mut self, id: GenerationalId, values: Vec<Box<dyn Component>>) -> Result<(), String> {
+        if values.is_empty() {
+            return Ok(());
+        }
+
+        let from = self.location(id);
+        let mut types = match from {
+            Some((a, _)) => self.archetypes[a].types.clone(),
+            None => Vec::new()
+        };
+
+        for v in values.iter() {
+            let t = (**v).as_any().type_id();
+            if !self.accepts(t) {
+                return Err(format!("No successfully registered ComponentManager for {}", v.type_name()));
+            }
+            if types.contains(&t) {
+                return Err(format!("Cannot attach multiple of the same component to Entity {}", id.id));
+            }
+            types.push(t);
+        }
+        types.sort_unstable();
+
+        let to = self.find_or_create(types);
+        match from {
+            Some((a, row)) => {
+                self.move_entity(id, a, row, Some(to));
+            },
+            None => self.push_entity(id, to)
+        }
+
+        for v in values {
+            let col = self.archetypes[to].column_index((*v).as_any().type_id()).unwrap();
+            self.archetypes[to].columns[col].push_dyn(v)?;
+        }
+
+        Ok(())
+    }
+
+    ///Removes the Component of type T of the given Entity and returns it, moving the Entity to its
+    ///new Archetype.
+    pub fn remove<T: Component>(&mut self, id: GenerationalId) -> Result<T, String> {
+        match self.remove_dyn(id, TypeId::of::<T>())?.downcast::<T>() {
+            Ok(c) => Ok(*c),
+            Err(_) => Err(format!("Entity {} does not have a {}", id.id, type_name::<T>()))
+        }
+    }
+
+    ///Removes the Component of type t of the given Entity and returns it as a trait object, moving
+    ///the Entity to its new Archetype.
+    pub fn remove_dyn(&mut self, id: GenerationalId, t: TypeId) -> Result<Box<dyn Component>, String> {
+        let (a, row) = match self.location(id) {
+            Some((a, row)) if self.archetypes[a].has_type(t) => (a, row),
+            _ => return Err(format!("Entity {} does not have a stored Component of that type", id.id))
+        };
+
+        let to = self.target_without(a, t);
+        let mut left = self.move_entity(id, a, row, to);
+        Ok(left.pop().unwrap())
+    }
+
+    ///Removes every stored Component of the given Entity and returns them as trait objects.
+    pub fn take_all(&mut self, id: GenerationalId) -> Vec<Box<dyn Component>> {
+        match self.location(id) {
+            Some((a, row)) => self.move_entity(id, a, row, None),
+            None => Vec::new()
+        }
+    }
+
+    ///Returns a copy of every stored Component of the given Entity as trait objects.
+    pub fn clone_all(&self, id: GenerationalId) -> Vec<Box<dyn Component>> {
+        match self.location(id) {
+            Some((a, row)) => self.archetypes[a].columns.iter().map(|c| c.get_dyn(row).dynamic_clone()).collect(),
+            None => Vec::new()
+        }
+    }
+
+    ///Moves the stored Components of each old owner over to its new owner, in order. Every
+    ///Entity is taken out before any is placed again, so owners may be swapped or shifted freely.
+    pub fn remap(&mut self, remap: &[(GenerationalId, GenerationalId)]) {
+        let moved: Vec<(GenerationalId, (usize, usize))> = remap.iter()
+            .filter_map(|(old, new)| self.location(*old).map(|loc| (*new, loc)))
+            .collect();
+
+        for (_, (a, row)) in moved.iter() {
+            let old = self.archetypes[*a].entities[*row];
+            self.locations[old.id as usize] = None;
+        }
+
+        for (new, (a, row)) in moved {
+            let arch = &mut self.archetypes[a];
+            arch.entities[row] = new;
+            for c in arch.columns.iter_mut() {
+                c.set_owner(row, new);
+            }
+
+            self.set_location(new, Some((a, row)));
+        }
+    }
+
+    ///Returns an iterator over the owner and Component of every stored Component of type T.
+    pub fn iter<T: Component>(&self) -> impl Iterator<Item = (GenerationalId, &T)> {
+        self.archetypes.iter()
+            .filter_map(|a| a.column::<T>().map(|c| a.entities.iter().copied().zip(c.iter())))
+            .flatten()
+    }
+
+    ///Returns an iterator over the owner and Component of every stored Component of type T,
+    ///mutably.
+    pub fn iter_mut<T: Component>(&mut self) -> impl Iterator<Item = (GenerationalId, &mut T)> {
+        self.archetypes.iter_mut()
+            .filter_map(|a| {
+                let ids = a.entities.as_slice();
+                let i = a.types.binary_search(&TypeId::of::<T>()).ok()?;
+                let c = a.columns[i].downcast_mut::<Vec<T>>()?;
+                Some(ids.iter().copied().zip(c.iter_mut()))
+            })
+            .flatten()
+    }
+
+    ///Returns an iterator over the owner and Components of every Entity with stored Components of
+    ///both types A and B.
+    pub fn iter2<A: Component, B: Component>(&self) -> impl Iterator<Item = (GenerationalId, &A, &B)> {
+        self.archetypes.iter()
+            .filter_map(|a| {
+                let ca = a.column::<A>()?;
+                let cb = a.column::<B>()?;
+                Some(a.entities.iter().copied().zip(ca.iter()).zip(cb.iter()).map(|((id, x), y)| (id, x, y)))
+            })
+            .flatten()
+    }
+
+    ///Returns an iterator over the owner and Components of every Entity with stored Components of
+    ///both types A and B, mutably. A and B must be different types.
+    pub fn iter2_mut<A: Component, B: Component>(&mut self) -> impl Iterator<Item = (GenerationalId, &mut A, &mut B)> {
+        self.archetypes.iter_mut()
+            .filter_map(|a| {
+                let i = a.types.binary_search(&TypeId::of::<A>()).ok()?;
+                let j = a.types.binary_search(&TypeId::of::<B>()).ok()?;
+                if i == j {
+                    return None;
+                }
+
+                let ids = a.entities.as_slice();
+                let (x, y) = pair_mut(&mut a.columns, i, j);
+                let ca = x.downcast_mut::<Vec<A>>()?;
+                let cb = y.downcast_mut::<Vec<B>>()?;
+                Some(ids.iter().copied().zip(ca.iter_mut()).zip(cb.iter_mut()).map(|((id, x), y)| (id, x, y)))
+            })
+            .flatten()
+    }
+
+    //Moves the Entity into the Archetype with t added to its types and returns that Archetype and
+    //the column the new Component must be pushed onto.
+    fn prepare_insert(&mut self, id: GenerationalId, t: TypeId) -> Result<(usize, usize), String> {
+        let to = match self.location(id) {
+            Some((a, row)) => {
+                if self.archetypes[a].has_type(t) {
+                    return Err(format!("Cannot attach multiple of the same component to Entity {}", id.id));
+                }
+
+                let to = self.target_with(a, t);
+                self.move_entity(id, a, row, Some(to));
+                to
+            },
+            None => {
+                let to = self.find_or_create(vec![t]);
+                self.push_entity(id, to);
+                to
+            }
+        };
+
+        Ok((to, self.archetypes[to].column_index(t).unwrap()))
+    }
+
+    //Adds a row for an Entity with no stored Components to Archetype a. Its Components must be
+    //pushed onto every column right after.
+    fn push_entity(&mut self, id: GenerationalId, a: usize) {
+        self.archetypes[a].entities.push(id);
+        self.set_location(id, Some((a, self.archetypes[a].entities.len() - 1)));
+    }
+
+    //Moves the Entity in row of Archetype a into Archetype to, or out of every Archetype for None.
+    //Returns the Components whose type to does not store.
+    fn move_entity(&mut self, id: GenerationalId, a: usize, row: usize, to: Option<usize>) -> Vec<Box<dyn Component>> {
+        let mut left = Vec::new();
+
+        match to {
+            Some(b) => {
+                let (src, dest) = pair_mut(&mut self.archetypes, a, b);
+
+                for (t, c) in src.types.iter().zip(src.columns.iter_mut()) {
+                    match dest.column_index(*t) {
+                        Some(j) => c.move_row(row, &mut *dest.columns[j]),
+                        None => left.push(c.swap_remove_dyn(row))
+                    }
+                }
+
+                dest.entities.push(id);
+                let new_row = dest.entities.len() - 1;
+                self.set_location(id, Some((b, new_row)));
+            },
+            None => {
+                for c in self.archetypes[a].columns.iter_mut() {
+                    left.push(c.swap_remove_dyn(row));
+                }
+
+                self.set_location(id, None);
+            }
+        }
+
+        let src = &mut self.archetypes[a];
+        src.entities.swap_remove(row);
+        if row < src.entities.len() {
+            let swapped = src.entities[row];
+            self.set_location(swapped, Some((a, row)));
+        }
+
+        left
+    }
+
+    fn set_location(&mut self, id: GenerationalId, loc: Option<(usize, usize)>) {
+        let i = id.id as usize;
+        if self.locations.len() <= i {
+            self.locations.resize(i + 1, None);
+        }
+        self.locations[i] = loc;
+    }
+
+    //Returns the Archetype with the types of Archetype a plus t.
+    fn target_with(&mut self, a: usize, t: TypeId) -> usize {
+        if let Some(to) = self.archetypes[a].add_edges.get(&t) {
+            return *to;
+        }
+
+        let mut types = self.archetypes[a].types.clone();
+        types.push(t);
+        types.sort_unstable();
+
+        let to = self.find_or_create(types);
+        self.archetypes[a].add_edges.insert(t, to);
+        to
+    }
+
+    //Returns the Archetype with the types of Archetype a minus t, or None if no types are left.
+    fn target_without(&mut self, a: usize, t: TypeId) -> Option<usize> {
+        if let Some(to) = self.archetypes[a].remove_edges.get(&t) {
+            return *to;
+        }
+
+        let types: Vec<TypeId> = self.archetypes[a].types.iter().copied().filter(|o| *o != t).collect();
+        let to = if types.is_empty() {
+            None
+        } else {
+            Some(self.find_or_create(types))
+        };
+
+        self.archetypes[a].remove_edges.insert(t, to);
+        to
+    }
+
+    //Returns the Archetype for the given sorted set of types, creating it if needed.
+    fn find_or_create(&mut self, types: Vec<TypeId>) -> usize {
+        if let Some(a) = self.index.get(&types) {
+            return *a;
+        }
+
+        let columns = types.iter().map(|t| (self.factories[t])()).collect();
+        self.archetypes.push(Archetype {
+            types: types.clone(),
+            columns,
+            entities: Vec::new(),
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new()
+        });
+
+        let a = self.archetypes.len() - 1;
+        self.index.insert(types, a);
+        a
+    }
+}
+
+fn new_column<T: Component>() -> Box<dyn Column> {
+    Box::new(Vec::<T>::new())
+}
+
+//Returns mutable references to two different elements of a slice.
+fn pair_mut<T>(v: &mut [T], i: usize, j: usize) -> (&mut T, &mut T) {
+    if i < j {
+        let (l, r) = v.split_at_mut(j);
+        (&mut l[i], &mut r[0])
+    } else {
+        let (l, r) = v.split_at_mut(i);
+        (&mut r[0], &mut l[j])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::test_components::*;
+
+    fn id(i: u32, gen: u32) -> GenerationalId {
+        GenerationalId::new(i, gen)
+    }
+
+    fn storage() -> ArchetypeStorage {
+        let mut s = ArchetypeStorage::new();
+        s.register::<Position>();
+        s.register::<Velocity>();
+        s
+    }
+
+    //Returns an ArchetypeStorage with a Position for each of the first n slots, with x set to the
+    //slot, all in a single Archetype.
+    fn positioned(n: u32) -> ArchetypeStorage {
+        let mut s = storage();
+        for i in 0..n {
+            s.insert(id(i, 1), Position::new(id(i, 1), i as i32)).unwrap();
+        }
+        s
+    }
+
+    #[test]
+    fn unregistered_types_are_rejected() {
+        let mut s = ArchetypeStorage::new();
+        s.register::<Position>();
+
+        assert!(!s.accepts(TypeId::of::<Velocity>()));
+        assert!(s.insert_dyn(id(0, 1), Box::new(Velocity::new(id(0, 1), 1))).is_err());
+        assert!(s.location(id(0, 1)).is_none());
+    }
+
+    #[test]
+    fn entity_moves_into_new_archetype_and_back() {
+        let mut s = positioned(3);
+
+        s.insert(id(0, 1), Velocity::new(id(0, 1), 5)).unwrap();
+
+        assert_eq!(s.archetypes().len(), 2);
+        let (a, row) = s.location(id(0, 1)).unwrap();
+        assert_eq!(s.archetypes()[a].types().len(), 2);
+        assert_eq!(row, 0);
+        assert_eq!(s.get::<Position>(id(0, 1)).unwrap().x, 0);
+        assert_eq!(s.get::<Velocity>(id(0, 1)).unwrap().dx, 5);
+        assert_eq!(s.archetypes()[0].entities(), &[id(2, 1), id(1, 1)]);
+        assert_eq!(s.get::<Position>(id(2, 1)).unwrap().x, 2);
+        assert_eq!(s.iter2::<Position, Velocity>().count(), 1);
+
+        assert_eq!(s.remove::<Velocity>(id(0, 1)).unwrap().dx, 5);
+
+        assert_eq!(s.archetypes().len(), 2);
+        assert_eq!(s.location(id(0, 1)), Some((0, 2)));
+        assert!(s.archetypes()[a].is_empty());
+        assert_eq!(s.get::<Position>(id(0, 1)).unwrap().x, 0);
+        assert!(s.get::<Velocity>(id(0, 1)).is_none());
+        assert_eq!(s.iter::<Position>().count(), 3);
+    }
+
+    #[test]
+    fn insert_all_moves_once() {
+        let mut s = storage();
+        let both: Vec<Box<dyn Component>> = vec![Box::new(Velocity::new(id(0, 1), 5)), Box::new(Position::new(id(0, 1), 4))];
+        s.insert_all_dyn(id(0, 1), both).unwrap();
+
+        assert_eq!(s.archetypes().len(), 1);
+        assert_eq!(s.types_of(id(0, 1)).len(), 2);
+        assert_eq!(s.get::<Position>(id(0, 1)).unwrap().x, 4);
+        assert_eq!(s.get::<Velocity>(id(0, 1)).unwrap().dx, 5);
+
+        s.insert(id(1, 1), Position::new(id(1, 1), 1)).unwrap();
+        s.insert_all_dyn(id(1, 1), vec![Box::new(Velocity::new(id(1, 1), 2))]).unwrap();
+        assert_eq!(s.archetypes().len(), 2);
+        assert_eq!(s.location(id(1, 1)), Some((0, 1)));
+        assert!(s.archetypes()[1].is_empty());
+    }
+
+    #[test]
+    fn insert_all_rejects_without_storing() {
+        let mut s = positioned(1);
+        let repeated: Vec<Box<dyn Component>> = vec![Box::new(Velocity::new(id(0, 1), 1)), Box::new(Velocity::new(id(0, 1), 2))];
+        let present: Vec<Box<dyn Component>> = vec![Box::new(Velocity::new(id(0, 1), 1)), Box::new(Position::new(id(0, 1), 2))];
+
+        assert!(s.insert_all_dyn(id(0, 1), repeated).is_err());
+        assert!(s.insert_all_dyn(id(0, 1), present).is_err());
+        assert_eq!(s.archetypes().len(), 1);
+        assert_eq!(s.get::<Position>(id(0, 1)).unwrap().x, 0);
+        assert!(s.get::<Velocity>(id(0, 1)).is_none());
+    }
+
+    #[test]
+    fn moves_follow_cached_edges() {
+        let mut s = positioned(3);
+        s.insert(id(0, 1), Velocity::new(id(0, 1), 1)).unwrap();
+        let both = s.location(id(0, 1)).unwrap().0;
+        let vel = TypeId::of::<Velocity>();
+
+        s.remove::<Velocity>(id(0, 1)).unwrap();
+        assert_eq!(s.archetypes[0].add_edges.get(&vel), Some(&both));
+        assert_eq!(s.archetypes[both].remove_edges.get(&vel), Some(&Some(0)));
+
+        //Breaking the index shows whether later moves look the set of types up again.
+        s.index.clear();
+        s.insert(id(1, 1), Velocity::new(id(1, 1), 2)).unwrap();
+        assert_eq!(s.location(id(1, 1)), Some((both, 0)));
+        s.remove::<Velocity>(id(1, 1)).unwrap();
+
+        assert_eq!(s.archetypes().len(), 2);
+        assert_eq!(s.archetypes()[0].len(), 3);
+    }
+
+    #[test]
+    fn removing_the_last_type_caches_no_archetype() {
+        let mut s = positioned(2);
+
+        s.remove::<Position>(id(0, 1)).unwrap();
+
+        assert!(s.location(id(0, 1)).is_none());
+        assert_eq!(s.archetypes[0].remove_edges.get(&TypeId::of::<Position>()), Some(&None));
+        assert_eq!(s.archetypes()[0].entities(), &[id(1, 1)]);
+        assert_eq!(s.location(id(1, 1)), Some((0, 0)));
+    }
+
+    #[test]
+    fn stale_generation_is_not_found() {
+        let mut s = positioned(2);
+
+        assert!(s.location(id(1, 2)).is_none());
+        assert!(s.get::<Position>(id(1, 2)).is_none());
+        assert!(s.remove::<Position>(id(1, 2)).is_err());
+        assert!(s.take_all(id(1, 2)).is_empty());
+        assert_eq!(s.get::<Position>(id(1, 1)).unwrap().x, 1);
+    }
+
+    #[test]
+    fn remap_swaps_and_shifts_owners() {
+        let mut s = positioned(3);
+        s.insert(id(2, 1), Velocity::new(id(2, 1), 9)).unwrap();
+
+        s.remap(&[(id(0, 1), id(1, 2)), (id(1, 1), id(0, 2)), (id(2, 1), id(40, 3))]);
+
+        assert_eq!(s.get::<Position>(id(1, 2)).unwrap().x, 0);
+        assert_eq!(s.get::<Position>(id(1, 2)).unwrap().get_owner(), id(1, 2));
+        assert_eq!(s.get::<Position>(id(0, 2)).unwrap().x, 1);
+        assert_eq!(s.get::<Velocity>(id(40, 3)).unwrap().get_owner(), id(40, 3));
+        assert_eq!(s.get::<Position>(id(40, 3)).unwrap().x, 2);
+        for old in [id(0, 1), id(1, 1), id(2, 1)] {
+            assert!(s.location(old).is_none());
+        }
+    }
+}
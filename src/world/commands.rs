@@ -0,0 +1,238 @@
+use crate::world::world::*;
+use crate::component::component::*;
+use crate::component::bundle::*;
+use crate::common::generational_id::*;
+
+use std::vec::Vec;
+use std::sync::RwLock;
+
+///A single recorded operation, applied to the World once it can be borrowed mutably.
+pub type Command = Box<dyn FnOnce(&mut World) -> Result<(), String> + Send + Sync>;
+
+///Storage for Commands recorded against a World that have not yet been applied.
+///
+///Ids handed out by Commands::spawn are kept separately from the other operations, as they must
+///be made live before any other change to the World's free queue.
+pub struct CommandQueue {
+    pub reserved: Vec<GenerationalId>,
+    pub ops: Vec<Command>
+}
+
+impl CommandQueue {
+    ///Creates a new, empty CommandQueue.
+    pub fn new() -> CommandQueue {
+        CommandQueue {
+            reserved: Vec::new(),
+            ops: Vec::new()
+        }
+    }
+}
+
+///A deferred command buffer for changes that need a mutable World.
+///
+///Systems only receive an immutable World, so any spawning, deleting, attaching or detaching they
+///do is recorded here instead. Recorded Commands are applied in the order they were recorded by
+///World::apply_commands, which is called at the end of SystemManager::execute and at the start of
+///World::update. The queue is only locked while a single Command is recorded, so any number of
+///Commands buffers may be held for the same World at once.
+pub struct Commands<'w> {
+    world: &'w World,
+    queue: &'w RwLock<CommandQueue>
+}
+
+#[allow(dead_code)]
+impl<'w> Commands<'w> {
+
+    ///Creates a new Commands buffer that records into the provided queue of world.
+    pub fn new(world: &'w World, queue: &'w RwLock<CommandQueue>) -> Commands<'w> {
+        Commands {
+            world,
+            queue
+        }
+    }
+
+    ///Reserves a GenerationalId for a new Entity and returns it immediately. The Entity becomes
+    ///active once the Commands are applied, or as soon as the World is next changed mutably.
+    pub fn spawn(&mut self) -> GenerationalId {
+        let mut queue = self.queue.write().unwrap();
+        let id = self.world.peek_free(queue.reserved.len());
+        queue.reserved.push(id);
+        id
+    }
+
+    ///Reserves a GenerationalId for a new Entity with every Component in bundle attached. If the
+    ///Bundle fails to attach once applied, the Entity is deleted again.
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> GenerationalId {
+        let id = self.spawn();
+
+        self.push(move |w: &mut World| {
+            let res = w.attach_bundle(id, bundle);
+            if res.is_err() {
+                let _ = w.delete(id);
+            }
+            res
+        });
+
+        id
+    }
+
+    ///Records the deletion of an Entity.
+    pub fn delete(&mut self, id: GenerationalId) {
+        self.push(move |w: &mut World| w.delete(id));
+    }
+
+    ///Records the attachment of a Component to an Entity, which may be one reserved by spawn.
+    pub fn attach<T: Component>(&mut self, id: GenerationalId, comp: T) {
+        self.push(move |w: &mut World| w.attach_component(id, comp));
+    }
+
+    ///Records the detachment of the Component of type T from an Entity.
+    pub fn detach<T: Component>(&mut self, id: GenerationalId) {
+        self.push(move |w: &mut World| w.detach_component::<T>(id));
+    }
+
+    ///Records an arbitrary operation on the World.
+    pub fn push<F>(&mut self, f: F)
+        where F: FnOnce(&mut World) -> Result<(), String> + Send + Sync + 'static
+    {
+        self.queue.write().unwrap().ops.push(Box::new(f));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::component_manager::*;
+    use crate::component::vec_storage::*;
+    use crate::component::test_components::*;
+    use crate::world::free_list::*;
+
+    fn world_with_holes(strategy: SlotReuse) -> World {
+        let mut w = World::new();
+        w.register_manager(VecStorage::<Position>::new());
+        w.set_slot_reuse(strategy);
+
+        let ids = w.spawn_batch(6);
+        for i in [3, 1, 4] {
+            w.delete(ids[i]).unwrap();
+        }
+        w
+    }
+
+    //Reserves n ids through Commands in one World and spawns n directly in an identical one, so
+    //every strategy must hand out the same ids both ways.
+    fn reserved_matches_spawn(strategy: SlotReuse, n: usize) {
+        let mut a = world_with_holes(strategy);
+        let mut b = world_with_holes(strategy);
+
+        let reserved: Vec<GenerationalId> = {
+            let mut cmd = a.commands();
+            (0..n).map(|_| cmd.spawn()).collect()
+        };
+        let spawned: Vec<GenerationalId> = (0..n).map(|_| b.spawn()).collect();
+
+        assert_eq!(reserved, spawned);
+
+        a.apply_commands().unwrap();
+        assert!(reserved.iter().all(|id| a.is_alive(*id)));
+        assert_eq!(a.entity_count(), b.entity_count());
+    }
+
+    #[test]
+    fn reserved_ids_follow_lifo() {
+        reserved_matches_spawn(SlotReuse::Lifo, 5);
+    }
+
+    #[test]
+    fn reserved_ids_follow_fifo() {
+        reserved_matches_spawn(SlotReuse::Fifo { min_free: 1 }, 5);
+    }
+
+    #[test]
+    fn reserved_ids_follow_lowest_index() {
+        reserved_matches_spawn(SlotReuse::LowestIndex, 5);
+    }
+
+    #[test]
+    fn reserved_ids_become_live_before_other_mutation() {
+        let mut w = world_with_holes(SlotReuse::Lifo);
+
+        let id = w.commands().spawn();
+        let other = w.spawn();
+
+        assert!(w.is_alive(id));
+        assert_ne!(id, other);
+    }
+
+    #[test]
+    fn commands_apply_in_order() {
+        let mut w = world_with_holes(SlotReuse::Lifo);
+
+        let id = {
+            let mut cmd = w.commands();
+            let id = cmd.spawn();
+            cmd.attach(id, Position::new(id, 1));
+            cmd.detach::<Position>(id);
+            cmd.attach(id, Position::new(id, 2));
+            id
+        };
+
+        //The detach is deferred, but already hides the first Position, so the second attach
+        //takes its place.
+        w.apply_commands().unwrap();
+        {
+            let m = w.manager::<Position>().unwrap();
+            assert_eq!(downcast_read_lock::<Position>(&m).fetch(id).unwrap().x, 2);
+        }
+
+        w.update().unwrap();
+        let m = w.manager::<Position>().unwrap();
+        assert_eq!(downcast_read_lock::<Position>(&m).fetch(id).unwrap().x, 2);
+    }
+
+    #[test]
+    fn several_buffers_at_once() {
+        let mut w = world_with_holes(SlotReuse::Lifo);
+
+        let (a, b) = {
+            let mut first = w.commands();
+            let mut second = w.commands();
+            (first.spawn(), second.spawn())
+        };
+
+        assert_ne!(a, b);
+        w.apply_commands().unwrap();
+        assert!(w.is_alive(a) && w.is_alive(b));
+    }
+
+    #[test]
+    fn failed_commands_are_returned() {
+        let mut w = world_with_holes(SlotReuse::Lifo);
+        let live = w.spawn();
+
+        {
+            let mut cmd = w.commands();
+            cmd.detach::<Position>(live);
+            cmd.attach(live, Position::new(live, 0));
+            cmd.delete(GenerationalId::new(40, 1));
+        }
+
+        let err = w.update().unwrap_err();
+        assert_eq!(err.lines().count(), 2);
+
+        let m = w.manager::<Position>().unwrap();
+        assert!(downcast_read_lock::<Position>(&m).has_component(live));
+    }
+
+    #[test]
+    fn spawn_with_is_undone_when_bundle_fails() {
+        let mut w = World::new();
+        w.register_manager(VecStorage::<Position>::new());
+
+        let id = w.commands().spawn_with((Position::new(GenerationalId::new(0, 0), 1), Velocity::new(GenerationalId::new(0, 0), 1)));
+
+        assert!(w.apply_commands().is_err());
+        assert!(!w.is_alive(id));
+        assert_eq!(w.entity_count(), 0);
+    }
+}
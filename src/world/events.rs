@@ -0,0 +1,181 @@
+use crate::world::resource::*;
+
+use std::vec::Vec;
+use std::marker::PhantomData;
+
+//An event along with its position in the sequence of every event of its type ever sent.
+struct EventInstance<E> {
+    id: usize,
+    event: E
+}
+
+///Double-buffered storage for events of type E sent between Systems.
+///
+///Events sent during a frame go into the current buffer. Each World::update swaps the buffers and
+///drops the oldest one, so an event lives for two updates, which is long enough for every System
+///to see it once no matter the order the sender and receiver run in.
+///
+pub struct Events<E> {
+    previous: Vec<EventInstance<E>>,
+    current: Vec<EventInstance<E>>,
+    count: usize
+}
+
+#[allow(dead_code)]
+impl<E: Resource> Events<E> {
+
+    ///Creates a new, empty Events storage.
+    pub fn new() -> Events<E> {
+        Events {
+            previous: Vec::new(),
+            current: Vec::new(),
+            count: 0
+        }
+    }
+
+    ///Sends an event to every EventReader of this type.
+    pub fn send(&mut self, event: E) {
+        self.current.push(EventInstance {
+            id: self.count,
+            event
+        });
+        self.count += 1;
+    }
+
+    ///Drops every event from before the previous update and starts a new current buffer.
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    ///Returns the number of events still stored.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    ///Returns whether or not any events are still stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+///A cursor into Events of type E that remembers which events it has already read.
+///
+///Each System that consumes events should own its own EventReader, so each System sees each event
+///exactly once.
+///
+pub struct EventReader<E> {
+    last: usize,
+    marker: PhantomData<E>
+}
+
+#[allow(dead_code)]
+impl<E: Resource> EventReader<E> {
+
+    ///Creates a new EventReader that will read every event still stored on its first read.
+    pub fn new() -> EventReader<E> {
+        EventReader {
+            last: 0,
+            marker: PhantomData
+        }
+    }
+
+    ///Returns every event sent since the last read, oldest first, and moves the cursor past them.
+    pub fn read<'a>(&mut self, events: &'a Events<E>) -> impl Iterator<Item = &'a E> {
+        let last = self.last;
+        self.last = events.count;
+
+        events.previous.iter()
+            .chain(events.current.iter())
+            .filter(move |i| i.id >= last)
+            .map(|i| &i.event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::world::*;
+
+    fn read_all(reader: &mut EventReader<u32>, events: &Events<u32>) -> Vec<u32> {
+        reader.read(events).copied().collect()
+    }
+
+    #[test]
+    fn events_live_for_two_updates() {
+        let mut events = Events::new();
+        events.send(1u32);
+
+        events.update();
+        assert_eq!(events.len(), 1);
+
+        events.update();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn each_reader_sees_each_event_once() {
+        let mut events = Events::new();
+        let mut early = EventReader::new();
+        let mut late = EventReader::new();
+
+        events.send(1u32);
+        assert_eq!(read_all(&mut early, &events), vec![1]);
+
+        events.update();
+        events.send(2);
+
+        assert_eq!(read_all(&mut early, &events), vec![2]);
+        assert_eq!(read_all(&mut late, &events), vec![1, 2]);
+        assert!(read_all(&mut early, &events).is_empty());
+        assert!(read_all(&mut late, &events).is_empty());
+    }
+
+    #[test]
+    fn reader_skips_dropped_events() {
+        let mut events = Events::new();
+        let mut reader = EventReader::new();
+
+        events.send(1u32);
+        events.update();
+        events.send(2);
+        events.update();
+        events.send(3);
+
+        assert_eq!(read_all(&mut reader, &events), vec![2, 3]);
+    }
+
+    #[test]
+    fn world_update_swaps_registered_events_once() {
+        let mut w = World::new();
+        w.register_event::<u32>();
+        w.register_event::<u32>();
+        w.send_event(1u32).unwrap();
+
+        w.update().unwrap();
+        assert_eq!(w.events::<u32>().unwrap().len(), 1);
+
+        w.update().unwrap();
+        assert!(w.events::<u32>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reregistering_after_removal_keeps_one_updater() {
+        let mut w = World::new();
+        w.register_event::<u32>();
+        w.remove_resource::<Events<u32>>();
+        w.register_event::<u32>();
+
+        w.send_event(1u32).unwrap();
+        w.update().unwrap();
+
+        //A second updater would have swapped the buffers twice and dropped the event already.
+        assert_eq!(w.events::<u32>().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn sending_unregistered_events_fails() {
+        let w = World::new();
+        assert!(w.send_event(1u32).is_err());
+        assert!(w.events::<u32>().is_none());
+    }
+}
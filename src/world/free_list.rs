@@ -0,0 +1,115 @@
+use crate::common::generational_id::*;
+
+use std::collections::{BTreeSet, VecDeque};
+
+///Defines the order in which freed Entity slots are handed out again.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum SlotReuse {
+    ///The most recently freed slot is reused first.
+    Lifo,
+    ///The least recently freed slot is reused first, and only once more than min_free slots are
+    ///free, so a freed slot rests for a while before it is reused.
+    Fifo { min_free: usize },
+    ///The free slot with the lowest index is reused first, keeping ids compact.
+    LowestIndex
+}
+
+///The freed Entity slots of a World, waiting to be reused according to a SlotReuse strategy.
+///
+///Only slots that have been used before are stored here. Once none are available, the World
+///appends new slots itself.
+///
+pub struct FreeList {
+    strategy: SlotReuse,
+    queue: VecDeque<GenerationalId>,
+    sorted: BTreeSet<GenerationalId>
+}
+
+#[allow(dead_code)]
+impl FreeList {
+
+    ///Creates a new, empty FreeList using the given strategy.
+    pub fn new(strategy: SlotReuse) -> FreeList {
+        FreeList {
+            strategy,
+            queue: VecDeque::new(),
+            sorted: BTreeSet::new()
+        }
+    }
+
+    ///Returns the current strategy.
+    pub fn strategy(&self) -> SlotReuse {
+        self.strategy
+    }
+
+    ///Changes the strategy, keeping every slot that is already free.
+    pub fn set_strategy(&mut self, strategy: SlotReuse) {
+        let free = self.drain();
+
+        self.strategy = strategy;
+        for id in free {
+            self.push(id);
+        }
+    }
+
+    ///Adds a freed slot, holding the GenerationalId it should be reused with.
+    pub fn push(&mut self, id: GenerationalId) {
+        match self.strategy {
+            SlotReuse::Lifo => self.queue.push_front(id),
+            SlotReuse::Fifo { .. } => self.queue.push_back(id),
+            SlotReuse::LowestIndex => {
+                self.sorted.insert(id);
+            }
+        }
+    }
+
+    ///Takes the next slot to reuse, if any is available.
+    pub fn pop(&mut self) -> Option<GenerationalId> {
+        if self.available() == 0 {
+            return None;
+        }
+
+        match self.strategy {
+            SlotReuse::LowestIndex => self.sorted.pop_first(),
+            _ => self.queue.pop_front()
+        }
+    }
+
+    ///Returns the slot that the k-th next pop will take, if that many are available.
+    pub fn peek(&self, k: usize) -> Option<GenerationalId> {
+        if k >= self.available() {
+            return None;
+        }
+
+        match self.strategy {
+            SlotReuse::LowestIndex => self.sorted.iter().nth(k).copied(),
+            _ => self.queue.get(k).copied()
+        }
+    }
+
+    ///Returns the number of free slots that may be taken right now.
+    pub fn available(&self) -> usize {
+        match self.strategy {
+            SlotReuse::Fifo { min_free } => self.queue.len().saturating_sub(min_free),
+            _ => self.len()
+        }
+    }
+
+    ///Returns the number of free slots, including any being held back.
+    pub fn len(&self) -> usize {
+        self.queue.len() + self.sorted.len()
+    }
+
+    ///Returns whether or not there are no free slots at all.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///Takes every free slot at once, including any being held back, in no particular order.
+    pub fn drain(&mut self) -> Vec<GenerationalId> {
+        let mut free: Vec<GenerationalId> = self.queue.drain(..).collect();
+        free.extend(std::mem::take(&mut self.sorted));
+        free
+    }
+}
@@ -1 +1,6 @@
 pub mod world;
+pub mod commands;
+pub mod resource;
+pub mod events;
+pub mod free_list;
+pub mod archetype;
@@ -0,0 +1,69 @@
+use downcast_rs::*;
+
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+///The trait for any singleton data stored directly in the World rather than attached to an Entity.
+///
+///Resources hold global state such as the score, the game clock or settings. Any thread-safe type
+///is a Resource, and the World stores at most one Resource of each type.
+///
+pub trait Resource: DowncastSync {}
+impl_downcast!(sync Resource);
+
+impl<T: Send + Sync + 'static> Resource for T {}
+
+///An immutable reference to a Resource of type R, holding a read lock on it while alive.
+pub struct ResourceRef<'a, R: Resource> {
+    guard: RwLockReadGuard<'a, Box<dyn Resource>>,
+    marker: PhantomData<R>
+}
+
+impl<'a, R: Resource> ResourceRef<'a, R> {
+    ///Wraps a read lock on storage that is known to hold a Resource of type R.
+    pub fn new(guard: RwLockReadGuard<'a, Box<dyn Resource>>) -> ResourceRef<'a, R> {
+        ResourceRef {
+            guard,
+            marker: PhantomData
+        }
+    }
+}
+
+impl<'a, R: Resource> Deref for ResourceRef<'a, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        (**self.guard).downcast_ref::<R>().unwrap()
+    }
+}
+
+///A mutable reference to a Resource of type R, holding a write lock on it while alive.
+pub struct ResourceMut<'a, R: Resource> {
+    guard: RwLockWriteGuard<'a, Box<dyn Resource>>,
+    marker: PhantomData<R>
+}
+
+impl<'a, R: Resource> ResourceMut<'a, R> {
+    ///Wraps a write lock on storage that is known to hold a Resource of type R.
+    pub fn new(guard: RwLockWriteGuard<'a, Box<dyn Resource>>) -> ResourceMut<'a, R> {
+        ResourceMut {
+            guard,
+            marker: PhantomData
+        }
+    }
+}
+
+impl<'a, R: Resource> Deref for ResourceMut<'a, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        (**self.guard).downcast_ref::<R>().unwrap()
+    }
+}
+
+impl<'a, R: Resource> DerefMut for ResourceMut<'a, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        (**self.guard).downcast_mut::<R>().unwrap()
+    }
+}
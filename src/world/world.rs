@@ -1,14 +1,25 @@
 use crate::entity::entity::*;
 use crate::component::component::*;
 use crate::component::component_manager::*;
+use crate::component::bundle::*;
+use crate::component::tracked_manager::*;
+use crate::component::tag_storage::*;
 use crate::common::generational_id::*;
+use crate::world::commands::*;
+use crate::world::resource::*;
+use crate::world::events::*;
+use crate::world::free_list::*;
+use crate::world::archetype::*;
+use crate::query::query::*;
+use crate::name_component::*;
 
 use std::string::*;
 use std::str::*;
 use std::vec::Vec;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::any::*;
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::u32;
 
 ///Manages Entitys and Components and is responsible for organizing the game world.
@@ -19,9 +30,22 @@ use std::u32;
 ///
 pub struct World {
     entities: Vec<Entity>,
-    free_queue: VecDeque<GenerationalId>,
+    children: Vec<Vec<GenerationalId>>,
+    free_queue: FreeList,
+    alive: usize,
+    gen_policy: GenerationPolicy,
+    retired: usize,
+    fresh_gen: u32,
     component_managers: HashMap<TypeId, RwLock<Box<dyn GeneralComponentManager>>>,
-    quit: bool
+    tag_proxies: HashMap<TypeId, TypeId>,
+    storage_mode: StorageMode,
+    archetypes: RwLock<ArchetypeStorage>,
+    resources: HashMap<TypeId, RwLock<Box<dyn Resource>>>,
+    event_updaters: HashMap<TypeId, fn(&World)>,
+    commands: RwLock<CommandQueue>,
+    change_tick: Arc<AtomicU32>,
+    last_run: u32,
+    quit: RwLock<Option<String>>
 }
 
 #[allow(dead_code)]
@@ -30,52 +54,247 @@ impl World {
     ///Creates a new World with a valid starting state.
     pub fn new() -> World {
         let ents: Vec<Entity> = Vec::new();
-        let mut free: VecDeque<GenerationalId> = VecDeque::new();
-        free.push_front(GenerationalId::new(0, 1));
+        let free = FreeList::new(SlotReuse::Lifo);
 
         let comp_mans: HashMap<TypeId, RwLock<Box<dyn GeneralComponentManager>>> = HashMap::new();
 
         World {
             entities: ents,
+            children: Vec::new(),
             free_queue: free,
+            alive: 0,
+            gen_policy: GenerationPolicy::Retire,
+            retired: 0,
+            fresh_gen: 1,
             component_managers: comp_mans,
-            quit: false
+            tag_proxies: HashMap::new(),
+            storage_mode: StorageMode::Managers,
+            archetypes: RwLock::new(ArchetypeStorage::new()),
+            resources: HashMap::new(),
+            event_updaters: HashMap::new(),
+            commands: RwLock::new(CommandQueue::new()),
+            change_tick: Arc::new(AtomicU32::new(1)),
+            last_run: 0,
+            quit: RwLock::new(None)
         }
     }
 
     ///Generates a new Entity and returns its GenerationalId.
     pub fn spawn(&mut self) -> GenerationalId {
-        let id = self.free_queue.pop_front().unwrap();
+        self.flush_reserved();
+        self.alloc()
+    }
+
+    //Takes the next GenerationalId off of the free queue, or a new slot if none is available, and
+    //makes its Entity active.
+    fn alloc(&mut self) -> GenerationalId {
+        let id = match self.free_queue.pop() {
+            Some(id) => id,
+            None => GenerationalId::new(self.entities.len() as u32, self.fresh_gen)
+        };
 
         if self.entities.len() > id.id as usize {
-            self.entities[id.id as usize].id = id;
+            self.entities[id.id as usize] = Entity::new(id);
+            self.children[id.id as usize].clear();
         } else {
             self.entities.push(Entity::new(id));
+            self.children.push(Vec::new());
+        }
+
+        self.alive += 1;
+        id
+    }
+
+    ///Generates a new Entity with every Component in bundle attached and returns its GenerationalId.
+    ///
+    ///Every ComponentManager the Bundle needs, and whether each will accept its Component, is
+    ///checked for before the Entity is allocated. If any Component still fails to attach, the Entity
+    ///and everything attached so far is rolled back, and the slot moves on to its next generation.
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> Result<GenerationalId, String> {
+        self.check_bundle::<B>()?;
+        self.check_insert_all(&bundle.components())?;
+
+        let id = self.spawn();
+
+        match self.attach_bundle(id, bundle) {
+            Ok(()) => Ok(id),
+            Err(e) => {
+                self.unspawn(id);
+                Err(e)
+            }
         }
+    }
 
-        if self.free_queue.len() == 0 {
-            self.free_queue.push_back(GenerationalId::new(self.entities.len() as u32, 1));
+    ///Attaches every Component in bundle to the Entity with the given Id. If any of them fails to
+    ///attach, every Component of the Bundle attached so far is removed again.
+    pub fn attach_bundle<B: Bundle>(&self, handle: GenerationalId, bundle: B) -> Result<(), String> {
+        if !self.is_alive(handle) {
+            return Err(format!("Entity ID {} is not active", handle.id))
         }
 
-        id
+        self.check_bundle::<B>()?;
+
+        let types = B::component_types();
+        for (t, name) in types.iter() {
+            if self.has_component_of(handle, *t) {
+                return Err(format!("Entity {} already has a {}", handle.id, name));
+            }
+        }
+        self.check_insert_all(&bundle.components())?;
+
+        let res = bundle.attach_all(self, handle);
+
+        if res.is_err() {
+            //Nothing of these types was attached beforehand, so anything present now came from the Bundle.
+            for (t, _) in types.iter() {
+                match self.component_managers.get(t) {
+                    Some(m) => {
+                        let _ = m.write().unwrap().general_delete_now(handle);
+                    },
+                    None => {
+                        let _ = self.archetypes.write().unwrap().remove_dyn(handle, *t);
+                    }
+                }
+            }
+        }
+
+        res
+    }
+
+    //Checks that every Component type in the Bundle has a registered ComponentManager, unless they
+    //are stored in Archetypes, and that no type appears more than once.
+    fn check_bundle<B: Bundle>(&self) -> Result<(), String> {
+        let types = B::component_types();
+
+        for (i, (t, name)) in types.iter().enumerate() {
+            if !self.component_managers.contains_key(t) && self.storage_mode != StorageMode::Archetypes {
+                return Err(format!("No successfully registered ComponentManager for {}", name));
+            }
+            if types[..i].iter().any(|(o, _)| o == t) {
+                return Err(format!("Cannot attach multiple of the same component {} in one Bundle", name));
+            }
+        }
+
+        Ok(())
+    }
+
+    //Checks that every Component would be accepted by the ComponentManager for its type from an
+    //Entity that has none of them yet, without inserting anything.
+    fn check_insert_all(&self, comps: &[&dyn Component]) -> Result<(), String> {
+        let mut by_type: HashMap<TypeId, Vec<&dyn Component>> = HashMap::new();
+        for c in comps.iter() {
+            by_type.entry(self.storage_type(*c)).or_default().push(*c);
+        }
+
+        for (t, v) in by_type {
+            if let Some(m) = self.component_managers.get(&t) {
+                m.read().unwrap().general_check_insert(&v)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    //Removes a freshly spawned Entity whose Components failed to attach. Any hooks already run and
+    //removals recorded refer to its GenerationalId, so the slot still moves on to its next
+    //generation rather than handing the same GenerationalId out again.
+    fn unspawn(&mut self, id: GenerationalId) {
+        for m in self.component_managers.values_mut() {
+            let _ = m.write().unwrap().general_delete_now(id);
+        }
+        self.archetypes.get_mut().unwrap().take_all(id);
+
+        self.entities[id.id as usize].id.gen = 0;
+        self.alive -= 1;
+        self.release_slot(id);
+    }
+
+    //Hands the slot of a removed Entity back to the free queue with its next generation, or retires
+    //it according to the GenerationPolicy if its generations are exhausted.
+    fn release_slot(&mut self, id: GenerationalId) {
+        match self.next_gen(id.gen) {
+            Some(gen) => self.free_queue.push(GenerationalId::new(id.id, gen)),
+            None => {
+                if self.gen_policy == GenerationPolicy::Panic && cfg!(debug_assertions) {
+                    panic!("Entity slot {} has exhausted its generations", id.id);
+                }
+                self.retired += 1;
+            }
+        }
+    }
+
+    //Returns the generation a slot moves on to after gen, or None if it must be retired.
+    fn next_gen(&self, gen: u32) -> Option<u32> {
+        if gen < u32::MAX {
+            Some(gen + 1)
+        } else if self.gen_policy == GenerationPolicy::Wrap {
+            Some(1)
+        } else {
+            None
+        }
+    }
+
+    ///Generates n new Entities at once and returns their GenerationalIds. Recycled slots are used
+    ///first, then every remaining Entity is appended in a single pass.
+    pub fn spawn_batch(&mut self, n: usize) -> Vec<GenerationalId> {
+        self.flush_reserved();
+
+        let mut ids = Vec::with_capacity(n);
+        while ids.len() < n && self.free_queue.available() > 0 {
+            ids.push(self.alloc());
+        }
+
+        let start = self.entities.len();
+        let fresh = n - ids.len();
+        self.reserve(fresh);
+
+        for i in start..(start + fresh) {
+            let id = GenerationalId::new(i as u32, self.fresh_gen);
+            self.entities.push(Entity::new(id));
+            self.children.push(Vec::new());
+            ids.push(id);
+        }
+
+        self.alive += fresh;
+        ids
+    }
+
+    ///Reserves capacity for at least n more Entities to be spawned without reallocating.
+    pub fn reserve(&mut self, n: usize) {
+        self.entities.reserve(n);
+        self.children.reserve(n);
+    }
+
+    ///Returns an iterator over every active Entity, in order of their ids.
+    pub fn iter_entities(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.iter().filter(|e| e.id.gen != 0)
+    }
+
+    ///Returns the number of active Entities.
+    pub fn entity_count(&self) -> usize {
+        self.alive
     }
 
     ///Removes an Entity from the World, if provided a valid GenerationalId.
     pub fn delete(&mut self, id: GenerationalId) -> Result<(), String> {
-        if (id.id as usize) < self.entities.len() && id.gen == self.entities[id.id as usize].id.gen {
+        self.flush_reserved();
 
-            if id.gen == std::u32::MAX {
-                self.free_queue.push_front(GenerationalId::new(id.id, 1));
-            } else {
-                self.free_queue.push_front(GenerationalId::new(id.id, id.gen + 1));
-            }
+        if self.is_alive(id) {
 
             for m in self.component_managers.values_mut() {
                 let mut m = m.write().unwrap();
                 let _ = m.general_delete_now(id);
             }
+            self.archetypes.get_mut().unwrap().take_all(id);
+
+            self.unlink_parent(id);
+            for child in std::mem::take(&mut self.children[id.id as usize]) {
+                self.entities[child.id as usize].parent = None;
+            }
 
             self.entities[id.id as usize].id.gen = 0;
+            self.alive -= 1;
+            self.release_slot(id);
 
             Ok(())
         } else {
@@ -83,18 +302,374 @@ impl World {
         }
     }
 
+    ///Removes an Entity and every one of its descendants from the World, if provided a valid
+    ///GenerationalId.
+    pub fn delete_recursive(&mut self, id: GenerationalId) -> Result<(), String> {
+        if !self.is_alive(id) {
+            return Err(String::from_str("Not a valid Entity ID").unwrap());
+        }
+
+        let subtree = self.subtree(id);
+
+        //Deleting leaves first means no Entity is briefly orphaned along the way.
+        for e in subtree.into_iter().rev() {
+            self.delete(e)?;
+        }
+
+        Ok(())
+    }
+
+    ///Makes parent the parent of child, replacing any previous parent of child.
+    ///
+    ///Fails if either Entity is not active, or if parent is child itself or one of its
+    ///descendants, as that would create a cycle.
+    pub fn set_parent(&mut self, child: GenerationalId, parent: GenerationalId) -> Result<(), String> {
+        if !self.is_alive(child) {
+            return Err(format!("Entity ID {} is not active", child.id));
+        }
+        if !self.is_alive(parent) {
+            return Err(format!("Entity ID {} is not active", parent.id));
+        }
+        if child == parent || self.ancestors(parent).contains(&child) {
+            return Err(format!("Entity {} cannot be a child of its own descendant {}", child.id, parent.id));
+        }
+
+        self.unlink_parent(child);
+        self.entities[child.id as usize].parent = Some(parent);
+        self.children[parent.id as usize].push(child);
+
+        Ok(())
+    }
+
+    ///Detaches child from its parent, if it has one, making it a root Entity.
+    pub fn remove_parent(&mut self, child: GenerationalId) -> Result<(), String> {
+        if !self.is_alive(child) {
+            return Err(format!("Entity ID {} is not active", child.id));
+        }
+
+        self.unlink_parent(child);
+        Ok(())
+    }
+
+    ///Returns the parent of the given Entity, if it is active and has one.
+    pub fn parent(&self, id: GenerationalId) -> Option<GenerationalId> {
+        if self.is_alive(id) {
+            self.entities[id.id as usize].parent
+        } else {
+            None
+        }
+    }
+
+    ///Returns the direct children of the given Entity, in the order they were attached.
+    pub fn children(&self, id: GenerationalId) -> &[GenerationalId] {
+        if self.is_alive(id) {
+            &self.children[id.id as usize]
+        } else {
+            &[]
+        }
+    }
+
+    ///Returns every ancestor of the given Entity, starting with its parent and ending with the
+    ///root of its tree.
+    pub fn ancestors(&self, id: GenerationalId) -> Vec<GenerationalId> {
+        let mut v = Vec::new();
+        let mut cur = self.parent(id);
+
+        while let Some(p) = cur {
+            v.push(p);
+            cur = self.parent(p);
+        }
+
+        v
+    }
+
+    //Clears the parent link of an active Entity on both ends.
+    fn unlink_parent(&mut self, child: GenerationalId) {
+        if let Some(p) = self.entities[child.id as usize].parent.take() {
+            self.children[p.id as usize].retain(|c| *c != child);
+        }
+    }
+
+    ///Sets the order in which freed Entity slots are reused. Defaults to SlotReuse::Lifo.
+    pub fn set_slot_reuse(&mut self, strategy: SlotReuse) {
+        self.flush_reserved();
+        self.free_queue.set_strategy(strategy);
+    }
+
+    ///Returns the current SlotReuse strategy.
+    pub fn slot_reuse(&self) -> SlotReuse {
+        self.free_queue.strategy()
+    }
+
+    ///Renumbers every active Entity so they occupy the lowest usable slots in their current order,
+    ///dropping the free slots left at the end. Any pending Commands and deferred deletions are
+    ///applied first, without starting a new frame for World::removed. If a Command fails, its errors
+    ///are returned and nothing is renumbered.
+    ///
+    ///Returns a table mapping the old GenerationalId of every active Entity to its new one. An
+    ///Entity that moves takes a generation above any its new slot has had, and slots that were
+    ///retired stay retired, so no old GenerationalId is ever handed out again. Every GenerationalId
+    ///held outside the World must still be passed through the table.
+    pub fn compact(&mut self) -> Result<HashMap<GenerationalId, GenerationalId>, String> {
+        self.apply_commands()?;
+        for m in self.component_managers.values_mut() {
+            m.write().unwrap().general_flush();
+        }
+
+        //The lowest generation each slot may be given next, or None if it is retired.
+        let mut floor: Vec<Option<u32>> = self.entities.iter()
+            .map(|e| if e.id.gen != 0 { self.next_gen(e.id.gen) } else { None })
+            .collect();
+        let exhausted: Vec<bool> = self.entities.iter().zip(floor.iter())
+            .map(|(e, f)| e.id.gen != 0 && f.is_none())
+            .collect();
+        for f in self.free_queue.drain() {
+            floor[f.id as usize] = Some(f.gen);
+        }
+
+        let mut remap = HashMap::new();
+        let mut moved = Vec::new();
+        let mut occupied = vec![false; self.entities.len()];
+        let mut next = 0;
+
+        for e in self.entities.iter().filter(|e| e.id.gen != 0) {
+            let i = e.id.id as usize;
+            while next < i && (occupied[next] || floor[next].is_none()) {
+                next += 1;
+            }
+
+            let new_id = if next < i {
+                GenerationalId::new(next as u32, floor[next].unwrap())
+            } else {
+                e.id
+            };
+
+            occupied[new_id.id as usize] = true;
+            next = new_id.id as usize + 1;
+            remap.insert(e.id, new_id);
+            if new_id != e.id {
+                moved.push((e.id, new_id));
+            }
+        }
+
+        for m in self.component_managers.values_mut() {
+            m.write().unwrap().general_remap(&moved);
+        }
+        self.archetypes.get_mut().unwrap().remap(&moved);
+
+        //Trailing slots that may be reused are dropped, and appended again later with a generation
+        //above any they have had.
+        let mut len = self.entities.len();
+        while len > 0 && !occupied[len - 1] && floor[len - 1].is_some() {
+            len -= 1;
+            self.fresh_gen = self.fresh_gen.max(floor[len].unwrap());
+        }
+
+        let old_entities = std::mem::take(&mut self.entities);
+        let old_children = std::mem::take(&mut self.children);
+        self.entities = (0..len).map(|i| Entity::new(GenerationalId::new(i as u32, 0))).collect();
+        self.children = vec![Vec::new(); len];
+
+        for (e, c) in old_entities.into_iter().zip(old_children).filter(|(e, _)| e.id.gen != 0) {
+            let new_id = remap[&e.id];
+            let mut ent = Entity::new(new_id);
+            ent.parent = e.parent.map(|p| remap[&p]);
+            ent.enabled = e.enabled;
+
+            self.entities[new_id.id as usize] = ent;
+            self.children[new_id.id as usize] = c.iter().map(|c| remap[c]).collect();
+        }
+
+        for i in 0..len {
+            if occupied[i] {
+                continue;
+            }
+            match floor[i] {
+                Some(gen) => self.free_queue.push(GenerationalId::new(i as u32, gen)),
+                None if exhausted[i] => self.retired += 1,
+                None => ()
+            }
+        }
+
+        Ok(remap)
+    }
+
+    ///Sets what happens to an Entity slot once its generation is exhausted. Defaults to
+    ///GenerationPolicy::Retire.
+    pub fn set_generation_policy(&mut self, policy: GenerationPolicy) {
+        self.gen_policy = policy;
+    }
+
+    ///Returns the current GenerationPolicy.
+    pub fn generation_policy(&self) -> GenerationPolicy {
+        self.gen_policy
+    }
+
+    ///Returns the number of Entity slots that have been retired for good after exhausting their
+    ///generations.
+    pub fn retired_count(&self) -> usize {
+        self.retired
+    }
+
+    ///Enables or disables an Entity. A disabled Entity keeps all of its Components, but is skipped
+    ///by World::query and typed iteration such as World::for_each unless they include disabled
+    ///Entities explicitly.
+    pub fn set_enabled(&mut self, id: GenerationalId, enabled: bool) -> Result<(), String> {
+        if !self.is_alive(id) {
+            return Err(format!("Entity ID {} is not active", id.id));
+        }
+
+        self.entities[id.id as usize].enabled = enabled;
+        Ok(())
+    }
+
+    ///Returns whether or not a GenerationalId is active and enabled.
+    pub fn is_enabled(&self, id: GenerationalId) -> bool {
+        self.is_alive(id) && self.entities[id.id as usize].enabled
+    }
+
+    ///Returns every active Entity that fulfills the Query, in order of their ids. Disabled Entities
+    ///are skipped unless the Query includes them.
+    pub fn query(&self, q: &Query) -> Vec<GenerationalId> {
+        let mut types = Vec::new();
+        for qe in q.query.iter() {
+            collect_types(qe, &mut types);
+        }
+
+        let guards: HashMap<TypeId, std::sync::RwLockReadGuard<Box<dyn GeneralComponentManager>>> = types.into_iter()
+            .filter_map(|t| self.component_managers.get(&t).map(|m| (t, m.read().unwrap())))
+            .collect();
+        let archetypes = self.archetypes.read().unwrap();
+
+        self.iter_entities()
+            .filter(|e| e.enabled || q.include_disabled)
+            .filter(|e| q.query.iter().all(|qe| matches(qe, e.id, &guards, &archetypes)))
+            .map(|e| e.id)
+            .collect()
+    }
+
+    ///Runs f on every Component of type T whose Entity is enabled.
+    pub fn for_each<T: Component, F: FnMut(&T)>(&self, f: F) {
+        self.each(false, f);
+    }
+
+    ///Runs f on every Component of type T, including those of disabled Entities.
+    pub fn for_each_including_disabled<T: Component, F: FnMut(&T)>(&self, f: F) {
+        self.each(true, f);
+    }
+
+    ///Runs f on every Component of type T whose Entity is enabled, allowing it to be changed.
+    pub fn for_each_mut<T: Component, F: FnMut(&mut T)>(&self, f: F) {
+        self.each_mut(false, f);
+    }
+
+    ///Runs f on every Component of type T, including those of disabled Entities, allowing it to be
+    ///changed.
+    pub fn for_each_mut_including_disabled<T: Component, F: FnMut(&mut T)>(&self, f: F) {
+        self.each_mut(true, f);
+    }
+
+    //Storages that keep their Components contiguous are walked as a slice, skipping the boxed
+    //iterator.
+    fn each<T: Component, F: FnMut(&T)>(&self, include_disabled: bool, mut f: F) {
+        if let Some(m) = self.manager::<T>() {
+            let m = downcast_read_lock::<T>(&m);
+            let visit = |c: &T| {
+                if include_disabled || self.is_enabled(c.get_owner()) {
+                    f(c);
+                }
+            };
+
+            match m.as_slice() {
+                Some(s) => s.iter().for_each(visit),
+                None => m.iter().for_each(visit)
+            }
+        } else {
+            for (id, c) in self.archetypes.read().unwrap().iter::<T>() {
+                if include_disabled || self.is_enabled(id) {
+                    f(c);
+                }
+            }
+        }
+    }
+
+    //Only the Components f is actually run on are marked as changed, so the storage is walked
+    //through TrackedManager::inner_mut.
+    fn each_mut<T: Component, F: FnMut(&mut T)>(&self, include_disabled: bool, mut f: F) {
+        if let Some(mut m) = self.manager_mut::<T>() {
+            let m = tracked_write_lock::<T>(&mut m);
+            let mut visited = Vec::new();
+            let visit = |c: &mut T| {
+                if include_disabled || self.is_enabled(c.get_owner()) {
+                    f(c);
+                    visited.push(c.get_owner());
+                }
+            };
+
+            let inner = m.inner_mut();
+            if inner.as_slice().is_some() {
+                inner.as_mut_slice().unwrap().iter_mut().for_each(visit);
+            } else {
+                inner.iter_mut().for_each(visit);
+            }
+
+            for o in visited {
+                m.mark_changed(o);
+            }
+        } else {
+            for (id, c) in self.archetypes.write().unwrap().iter_mut::<T>() {
+                if include_disabled || self.is_enabled(id) {
+                    f(c);
+                }
+            }
+        }
+    }
+
+    ///Runs f on the Components of types A and B of every enabled Entity that has both, where both
+    ///are stored in Archetypes. Each Archetype holding both types is walked row by row.
+    pub fn for_each2<A: Component, B: Component, F: FnMut(&A, &B)>(&self, mut f: F) {
+        for (id, a, b) in self.archetypes.read().unwrap().iter2::<A, B>() {
+            if self.is_enabled(id) {
+                f(a, b);
+            }
+        }
+    }
+
+    ///Runs f on the Components of types A and B of every enabled Entity that has both, where both
+    ///are stored in Archetypes, allowing them to be changed. A and B must be different types.
+    pub fn for_each2_mut<A: Component, B: Component, F: FnMut(&mut A, &mut B)>(&self, mut f: F) {
+        for (id, a, b) in self.archetypes.write().unwrap().iter2_mut::<A, B>() {
+            if self.is_enabled(id) {
+                f(a, b);
+            }
+        }
+    }
+
     ///Returns whether or not a GenerationalId is active.
     pub fn is_alive(&self, id: GenerationalId) -> bool {
-        if (id.id as usize) < self.entities.len() {
+        if id.gen != 0 && (id.id as usize) < self.entities.len() {
             self.entities[id.id as usize].id.gen == id.gen
         } else {
             false
         }
     }
 
-    ///Registers a ComponentManager to the World.
+    ///Registers a ComponentManager to the World, wrapped in a TrackedManager for change detection.
+    ///
+    ///The storage is chosen per Component type by which ComponentManager is registered for it:
+    ///- VecStorage for Components most Entities have and that are iterated every frame. It is
+    ///  dense and iterates as a slice, but every fetch goes through a HashMap.
+    ///- SparseSetStorage for Components that are fetched by owner as often as they are iterated.
+    ///  It is just as dense, and a fetch is an array lookup, but its sparse pages cost memory
+    ///  across the whole range of Entity ids that have the Component.
+    ///- MapStorage for Components only a handful of Entities ever have. It only costs memory per
+    ///  Component, but iterating it is not contiguous.
+    ///- register_tag instead, for marker types holding no data at all.
+    ///
+    ///For example, world.register_manager(MapStorage::<PlayerController>::new()).
+    ///
     pub fn register_manager<T: ComponentManager>(&mut self, man: T) {
-        let dyn_man: Box<dyn ComponentManager<Data = T::Data>> = Box::new(man);
+        let dyn_man: Box<dyn ComponentManager<Data = T::Data>> = Box::new(TrackedManager::new(man, self.change_tick.clone()));
         self.component_managers.insert(TypeId::of::<T::Data>(), RwLock::new(Box::new(dyn_man)));
     }
 
@@ -122,58 +697,1385 @@ impl World {
         }
     }
 
-    ///Attaches a provided Component to the Entity with the given Id, if it exists.
-    pub fn attach_component<T: Component>(&self, handle: GenerationalId, comp: T) -> Result<(), String> {
+    ///Registers a TagStorage for the Tag T to the World, so T can be attached to Entities and used
+    ///in Queries with QueryElement::has.
+    pub fn register_tag<T: Tag>(&mut self) {
+        self.component_managers.insert(TypeId::of::<T>(), RwLock::new(Box::new(TagStorage::<T>::new())));
+        self.tag_proxies.insert(TypeId::of::<Tagged<T>>(), TypeId::of::<T>());
+    }
+
+    //Returns the TypeId of the ComponentManager comp is stored in: that of the Tag T for a
+    //Tagged<T> of a registered Tag, and that of its concrete type for anything else.
+    fn storage_type(&self, comp: &dyn Component) -> TypeId {
+        let t = comp.as_any().type_id();
+        self.tag_proxies.get(&t).copied().unwrap_or(t)
+    }
+
+    ///Attaches the Tag T to the Entity with the given Id, if it exists.
+    pub fn attach_tag<T: Tag>(&self, handle: GenerationalId) -> Result<(), String> {
 
         if !self.is_alive(handle) {
             return Err(format!("Entity ID {} is not active", handle.id))
         }
 
-        let manager = self.manager_mut::<T>();
+        match self.component_managers.get(&TypeId::of::<T>()) {
+            Some(m) => tag_write_lock::<T>(&mut m.write().unwrap()).insert(handle),
+            None => Err(format!("No successfully registered TagStorage for {}", type_name::<T>()))
+        }
+    }
 
-        let mut manager = match manager {
-            Some(man) => man,
-            None =>  return Err(format!("No successfully registered ComponentManager for {}", comp.type_name()))
-        };
+    ///Detaches the Tag T from the Entity with the given Id. A Tag holds no data, so it is removed
+    ///right away rather than on the next World::update.
+    pub fn detach_tag<T: Tag>(&self, handle: GenerationalId) -> Result<(), String> {
 
-        let manager = (*manager).downcast_mut::<Box<dyn ComponentManager<Data=T>>>().unwrap();
+        if !self.is_alive(handle) {
+            return Err(format!("Entity ID {} is not active", handle.id))
+        }
 
-        let mut comp = comp;
-        comp.set_owner(handle);
-        manager.insert(handle, comp)
+        match self.component_managers.get(&TypeId::of::<T>()) {
+            Some(m) => tag_write_lock::<T>(&mut m.write().unwrap()).delete(handle),
+            None => Err(format!("No successfully registered TagStorage for {}", type_name::<T>()))
+        }
     }
 
-    ///Returns a given Entity and its associated Components in trait object form, if it is active.
-    pub fn clone_components_of(&self, handle: GenerationalId) -> Result<(Entity, Vec<Box<dyn Component>>), String> {
-        if !self.is_alive(handle) {
-            Err(format!("Entity ID {} is not active", handle.id))
-        } else {
+    ///Returns whether or not the Entity with the given Id is active and has the Tag T.
+    pub fn has_tag<T: Tag>(&self, handle: GenerationalId) -> bool {
+        self.is_alive(handle) && self.component_managers.get(&TypeId::of::<T>())
+            .is_some_and(|m| tag_read_lock::<T>(&m.read().unwrap()).has_component(handle))
+    }
 
-            let mut v = Vec::new();
+    ///Returns every Entity with the Tag T, in order of their ids.
+    pub fn tagged<T: Tag>(&self) -> Vec<GenerationalId> {
+        match self.component_managers.get(&TypeId::of::<T>()) {
+            Some(m) => tag_read_lock::<T>(&m.read().unwrap()).iter().collect(),
+            None => Vec::new()
+        }
+    }
 
-            for m in self.component_managers.values() {
-                let r = m.read().unwrap();
-                match r.fetch_dyn(handle) {
-                    Some(c) => v.push(c.dynamic_clone()),
-                    None => continue
-                };
-            }
+    ///Returns the first Entity with a NameComponent of the given name, if any.
+    pub fn find_by_name(&self, name: &str) -> Option<GenerationalId> {
+        self.find_all_by_name(name).first().copied()
+    }
 
-            Ok((self.entities[handle.id as usize], v))
+    ///Returns every Entity with a NameComponent of the given name. Uses the name index when the
+    ///NameComponents are stored in a NameComponentManager, and searches every name otherwise.
+    pub fn find_all_by_name(&self, name: &str) -> Vec<GenerationalId> {
+        let m = match self.manager::<NameComponent>() {
+            Some(m) => m,
+            None => return Vec::new()
+        };
+        let names = tracked_read_lock::<NameComponent>(&m).inner();
+
+        match names.downcast_ref::<NameComponentManager>() {
+            Some(n) => n.find_all(name).to_vec(),
+            None => names.iter().filter(|n| n.name == name).map(|n| n.get_owner()).collect()
         }
     }
 
-    ///Performs any potentially deferred operations such as Entity creation or deletion and updates all ComponentManagers.
-    pub fn update(&mut self) {
-        for m in self.component_managers.values_mut() {
-            let _ = m.write().unwrap().general_update();
+    ///Sets where Components are stored when their type has no registered ComponentManager.
+    ///Defaults to StorageMode::Managers.
+    ///
+    ///In StorageMode::Archetypes, Entities with the same set of such Component types share an
+    ///Archetype, whose columns are iterated linearly by for_each2 and for_each2_mut. Attaching or
+    ///detaching one of these Components moves the Entity to another Archetype immediately, and
+    ///they are not tracked by change detection, hooks or World::removed. Registered
+    ///ComponentManagers keep storing their types in either mode, and Components already stored in
+    ///Archetypes stay there after switching back.
+    ///
+    pub fn set_storage_mode(&mut self, mode: StorageMode) {
+        self.storage_mode = mode;
+    }
+
+    ///Returns the current StorageMode.
+    pub fn storage_mode(&self) -> StorageMode {
+        self.storage_mode
+    }
+
+    ///Makes the Component type T storable in Archetypes before any Component of it is attached by
+    ///its concrete type, so it can be attached as a trait object, such as by World::merge.
+    pub fn register_archetype_component<T: Component>(&mut self) {
+        self.archetypes.get_mut().unwrap().register::<T>();
+    }
+
+    ///Returns an immutable reference to the ArchetypeStorage, for iterating over its columns directly.
+    pub fn archetypes(&self) -> std::sync::RwLockReadGuard<'_, ArchetypeStorage> {
+        self.archetypes.read().unwrap()
+    }
+
+    ///Returns a mutable reference to the ArchetypeStorage, for iterating over its columns directly.
+    pub fn archetypes_mut(&self) -> std::sync::RwLockWriteGuard<'_, ArchetypeStorage> {
+        self.archetypes.write().unwrap()
+    }
+
+    //Returns whether or not the given Entity has a Component of type t in either storage.
+    fn has_component_of(&self, handle: GenerationalId, t: TypeId) -> bool {
+        match self.component_managers.get(&t) {
+            Some(m) => m.read().unwrap().general_has_component(handle),
+            None => self.archetypes.read().unwrap().contains(handle, t)
         }
+    }
 
+    //Returns whether or not Components of type t given as trait objects can be stored.
+    fn accepts(&self, t: TypeId) -> bool {
+        self.component_managers.contains_key(&t) ||
+            (self.storage_mode == StorageMode::Archetypes && self.archetypes.read().unwrap().accepts(t))
     }
 
-    ///Returns whether or not the game should quit running.
-    pub fn should_quit(&self) -> bool {
-        self.quit
+    ///Returns the current change tick, which Components are stamped with when added or changed.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick.load(Ordering::Relaxed)
+    }
+
+    ///Advances the change tick and returns its new value. The change tick wraps around once it
+    ///reaches u32::MAX.
+    pub fn increment_change_tick(&mut self) -> u32 {
+        self.change_tick.fetch_add(1, Ordering::Relaxed).wrapping_add(1)
+    }
+
+    ///Returns the change tick of the last run of the System currently running. When no System is
+    ///running, or it has never run before, this is a tick older than that of any Component.
+    pub fn last_run_tick(&self) -> u32 {
+        self.last_run
+    }
+
+    ///Sets the tick returned by last_run_tick. Used by the SystemManager before running each System.
+    pub fn set_last_run_tick(&mut self, tick: u32) {
+        self.last_run = tick;
+    }
+
+    ///Returns the owners of every Component of type T added since the current System last ran, in
+    ///order of their ids. Fails if T is not stored in a ComponentManager, as changes are only
+    ///tracked there.
+    pub fn added<T: Component>(&self) -> Result<Vec<GenerationalId>, String> {
+        self.added_since::<T>(self.last_run)
+    }
+
+    ///Returns the owners of every Component of type T added or changed since the current System last
+    ///ran, in order of their ids. Fails if T is not stored in a ComponentManager.
+    pub fn changed<T: Component>(&self) -> Result<Vec<GenerationalId>, String> {
+        self.changed_since::<T>(self.last_run)
+    }
+
+    ///Returns the owners of every Component of type T added after the given tick. Fails if T is not
+    ///stored in a ComponentManager.
+    pub fn added_since<T: Component>(&self, tick: u32) -> Result<Vec<GenerationalId>, String> {
+        let m = self.tracked_manager::<T>()?;
+        let added = tracked_read_lock::<T>(&m).added_since(tick);
+        Ok(added)
     }
 
+    ///Returns the owners of every Component of type T added or changed after the given tick. Fails if
+    ///T is not stored in a ComponentManager.
+    pub fn changed_since<T: Component>(&self, tick: u32) -> Result<Vec<GenerationalId>, String> {
+        let m = self.tracked_manager::<T>()?;
+        let changed = tracked_read_lock::<T>(&m).changed_since(tick);
+        Ok(changed)
+    }
+
+    ///Returns the owners of every Component of type T removed during the frame before the last
+    ///World::update, for Systems that need to clean up after removed Components. Fails if T is not
+    ///stored in a ComponentManager.
+    pub fn removed<T: Component>(&self) -> Result<std::vec::IntoIter<GenerationalId>, String> {
+        let m = self.tracked_manager::<T>()?;
+        let removed = tracked_read_lock::<T>(&m).removed().to_vec();
+        Ok(removed.into_iter())
+    }
+
+    //Returns the ComponentManager for T, or why changes to T cannot be looked up.
+    fn tracked_manager<T: Component>(&self) -> Result<std::sync::RwLockReadGuard<'_, Box<dyn GeneralComponentManager>>, String> {
+        match self.manager::<T>() {
+            Some(m) => Ok(m),
+            None if self.archetypes.read().unwrap().accepts(TypeId::of::<T>()) => {
+                Err(format!("Changes to {} are not tracked while it is stored in Archetypes", type_name::<T>()))
+            },
+            None => Err(format!("No successfully registered ComponentManager for {}", type_name::<T>()))
+        }
+    }
+
+    ///Registers a hook to run with the owner and a reference to each Component of type T after it
+    ///is attached to an Entity.
+    pub fn on_add<T, F>(&self, hook: F) -> Result<(), String>
+        where T: Component, F: Fn(GenerationalId, &T) + Send + Sync + 'static
+    {
+        match self.manager_mut::<T>() {
+            Some(mut m) => {
+                tracked_write_lock::<T>(&mut m).on_add(Box::new(hook));
+                Ok(())
+            },
+            None => Err(format!("No successfully registered ComponentManager for {}", type_name::<T>()))
+        }
+    }
+
+    ///Registers a hook to run with the owner and a reference to each Component of type T just before
+    ///it is removed, whether by World::delete, a detach, or a deferred deletion flushed by
+    ///World::update.
+    pub fn on_remove<T, F>(&self, hook: F) -> Result<(), String>
+        where T: Component, F: Fn(GenerationalId, &T) + Send + Sync + 'static
+    {
+        match self.manager_mut::<T>() {
+            Some(mut m) => {
+                tracked_write_lock::<T>(&mut m).on_remove(Box::new(hook));
+                Ok(())
+            },
+            None => Err(format!("No successfully registered ComponentManager for {}", type_name::<T>()))
+        }
+    }
+
+    ///Stores a Resource in the World, returning the Resource of the same type it replaced, if any.
+    pub fn insert_resource<R: Resource>(&mut self, res: R) -> Option<R> {
+        let old = self.remove_resource::<R>();
+        self.resources.insert(TypeId::of::<R>(), RwLock::new(Box::new(res)));
+        old
+    }
+
+    ///Returns an immutable reference to the Resource of the given type, if one exists.
+    pub fn resource<R: Resource>(&self) -> Option<ResourceRef<'_, R>> {
+        self.resources.get(&TypeId::of::<R>()).map(|r| ResourceRef::new(r.read().unwrap()))
+    }
+
+    ///Returns a mutable reference to the Resource of the given type, if one exists.
+    pub fn resource_mut<R: Resource>(&self) -> Option<ResourceMut<'_, R>> {
+        self.resources.get(&TypeId::of::<R>()).map(|r| ResourceMut::new(r.write().unwrap()))
+    }
+
+    ///Removes the Resource of the given type from the World and returns it, if one exists.
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        let res = self.resources.remove(&TypeId::of::<R>())?;
+
+        match res.into_inner().unwrap().downcast::<R>() {
+            Ok(r) => Some(*r),
+            Err(_) => None
+        }
+    }
+
+    ///Registers Events of type E to the World, stored as a Resource and swapped every World::update.
+    ///Registering the same type again, even after its Resource was removed, keeps a single updater.
+    pub fn register_event<E: Resource>(&mut self) {
+        if self.resource::<Events<E>>().is_none() {
+            self.insert_resource(Events::<E>::new());
+            self.event_updaters.insert(TypeId::of::<E>(), update_events::<E>);
+        }
+    }
+
+    ///Sends an event of type E to every EventReader of that type.
+    pub fn send_event<E: Resource>(&self, event: E) -> Result<(), String> {
+        match self.resource_mut::<Events<E>>() {
+            Some(mut events) => {
+                events.send(event);
+                Ok(())
+            },
+            None => Err(format!("No registered Events for {}", type_name::<E>()))
+        }
+    }
+
+    ///Returns an immutable reference to the Events of type E, for reading with an EventReader.
+    pub fn events<E: Resource>(&self) -> Option<ResourceRef<'_, Events<E>>> {
+        self.resource::<Events<E>>()
+    }
+
+    ///Attaches a provided Component to the Entity with the given Id, if it exists. Without a
+    ///registered ComponentManager for T, the Component is stored in the Archetypes in
+    ///StorageMode::Archetypes.
+    pub fn attach_component<T: Component>(&self, handle: GenerationalId, comp: T) -> Result<(), String> {
+
+        if !self.is_alive(handle) {
+            return Err(format!("Entity ID {} is not active", handle.id))
+        }
+
+        let manager = self.manager_mut::<T>();
+        let mut comp = comp;
+        comp.set_owner(handle);
+
+        let mut manager = match manager {
+            Some(man) => man,
+            None if self.storage_mode == StorageMode::Archetypes => return self.archetypes.write().unwrap().insert(handle, comp),
+            None =>  return Err(format!("No successfully registered ComponentManager for {}", comp.type_name()))
+        };
+
+        let manager = (*manager).downcast_mut::<Box<dyn ComponentManager<Data=T>>>().unwrap();
+
+        manager.insert(handle, comp)
+    }
+
+    //Attaches a Component of a Bundle to the Entity if a ComponentManager stores its type. Otherwise,
+    //in StorageMode::Archetypes, it is set aside in staged, to be stored along with the rest of the
+    //Bundle by attach_staged in a single move.
+    pub(crate) fn attach_or_stage<T: Component>(&self, handle: GenerationalId, comp: T, staged: &mut Vec<Box<dyn Component>>) -> Result<(), String> {
+        if self.component_managers.contains_key(&TypeId::of::<T>()) || self.storage_mode != StorageMode::Archetypes {
+            return self.attach_component(handle, comp);
+        }
+
+        let mut comp = comp;
+        comp.set_owner(handle);
+        self.archetypes.write().unwrap().register::<T>();
+        staged.push(Box::new(comp));
+        Ok(())
+    }
+
+    //Stores every Component set aside by attach_or_stage in the Archetype for the Entity's new set
+    //of types.
+    pub(crate) fn attach_staged(&self, handle: GenerationalId, staged: Vec<Box<dyn Component>>) -> Result<(), String> {
+        if !self.is_alive(handle) {
+            return Err(format!("Entity ID {} is not active", handle.id))
+        }
+
+        self.archetypes.write().unwrap().insert_all_dyn(handle, staged)
+    }
+
+    ///Attaches a Component given as a trait object to the Entity with the given Id, if it exists. The
+    ///Component is routed to the ComponentManager for its concrete type.
+    pub fn attach_dyn(&self, handle: GenerationalId, comp: Box<dyn Component>) -> Result<(), String> {
+
+        if !self.is_alive(handle) {
+            return Err(format!("Entity ID {} is not active", handle.id))
+        }
+
+        match self.component_managers.get(&self.storage_type(&*comp)) {
+            Some(m) => m.write().unwrap().general_insert(handle, comp),
+            None if self.storage_mode == StorageMode::Archetypes => {
+                let mut comp = comp;
+                comp.set_owner(handle);
+                self.archetypes.write().unwrap().insert_dyn(handle, comp)
+            },
+            None => Err(format!("No successfully registered ComponentManager for {}", comp.type_name()))
+        }
+    }
+
+    ///Detaches the Component of type T from the Entity with the given Id. The removal goes through
+    ///ComponentManager::delete, so it may be deferred until the next World::update, except for
+    ///Components stored in the Archetypes, which are removed immediately.
+    pub fn detach_component<T: Component>(&self, handle: GenerationalId) -> Result<(), String> {
+
+        if !self.is_alive(handle) {
+            return Err(format!("Entity ID {} is not active", handle.id))
+        }
+
+        let mut manager = match self.manager_mut::<T>() {
+            Some(man) => man,
+            None if self.archetypes.read().unwrap().accepts(TypeId::of::<T>()) => {
+                return self.archetypes.write().unwrap().remove::<T>(handle).map(|_| ());
+            },
+            None => return Err(format!("No successfully registered ComponentManager for {}", type_name::<T>()))
+        };
+
+        downcast_write_lock::<T>(&mut manager).delete(handle)
+    }
+
+    ///Removes the Component of type T from the Entity with the given Id immediately and returns it,
+    ///with its owner cleared.
+    pub fn take_component<T: Component>(&self, handle: GenerationalId) -> Result<T, String> {
+
+        if !self.is_alive(handle) {
+            return Err(format!("Entity ID {} is not active", handle.id))
+        }
+
+        let mut comp = match self.manager_mut::<T>() {
+            Some(mut man) => downcast_write_lock::<T>(&mut man).take(handle)?,
+            None if self.archetypes.read().unwrap().accepts(TypeId::of::<T>()) => self.archetypes.write().unwrap().remove::<T>(handle)?,
+            None => return Err(format!("No successfully registered ComponentManager for {}", type_name::<T>()))
+        };
+        comp.set_owner(GenerationalId::new(0, 0));
+        Ok(comp)
+    }
+
+    ///Returns a given Entity and its associated Components in trait object form, if it is active.
+    pub fn clone_components_of(&self, handle: GenerationalId) -> Result<(Entity, Vec<Box<dyn Component>>), String> {
+        if !self.is_alive(handle) {
+            Err(format!("Entity ID {} is not active", handle.id))
+        } else {
+
+            let mut v = Vec::new();
+
+            for m in self.component_managers.values() {
+                let r = m.read().unwrap();
+                match r.fetch_dyn(handle) {
+                    Some(c) => v.push(c.dynamic_clone()),
+                    None => continue
+                };
+            }
+            v.extend(self.archetypes.read().unwrap().clone_all(handle));
+
+            Ok((self.entities[handle.id as usize], v))
+        }
+    }
+
+    ///Spawns a new Entity with a copy of every Component of the given Entity, and the same parent
+    ///and enabled state.
+    pub fn duplicate(&mut self, handle: GenerationalId) -> Result<GenerationalId, String> {
+        let (ent, comps) = self.clone_components_of(handle)?;
+        self.check_insert_all(&comps.iter().map(|c| &**c).collect::<Vec<_>>())?;
+
+        let id = self.spawn();
+        if let Err(e) = self.insert_all_dyn(id, comps) {
+            self.unspawn(id);
+            return Err(e);
+        }
+
+        self.entities[id.id as usize].enabled = ent.enabled;
+
+        if let Some(p) = ent.parent {
+            self.set_parent(id, p)?;
+        }
+
+        Ok(id)
+    }
+
+    ///Spawns a new Entity in other with a copy of every Component and the enabled state of the given
+    ///Entity. Fails without spawning anything if other has no ComponentManager for one of the
+    ///Components, or one of them would reject its copy.
+    pub fn duplicate_into(&self, handle: GenerationalId, other: &mut World) -> Result<GenerationalId, String> {
+        let (ent, comps) = self.clone_components_of(handle)?;
+
+        for c in comps.iter() {
+            if !other.accepts(other.storage_type(&**c)) {
+                return Err(format!("No successfully registered ComponentManager for {}", c.type_name()));
+            }
+        }
+        other.check_insert_all(&comps.iter().map(|c| &**c).collect::<Vec<_>>())?;
+
+        let id = other.spawn();
+        if let Err(e) = other.insert_all_dyn(id, comps) {
+            other.unspawn(id);
+            return Err(e);
+        }
+
+        other.entities[id.id as usize].enabled = ent.enabled;
+        Ok(id)
+    }
+
+    //Attaches every Component to the Entity with the given Id, stopping at the first failure.
+    fn insert_all_dyn(&self, handle: GenerationalId, comps: Vec<Box<dyn Component>>) -> Result<(), String> {
+        for c in comps {
+            self.attach_dyn(handle, c)?;
+        }
+
+        Ok(())
+    }
+
+    ///Moves an Entity and all of its descendants out of this World and into other, along with all
+    ///of their Components. The moved Entity loses its parent, but the tree below it is kept.
+    ///
+    ///Returns the new GenerationalId of the Entity and a table mapping the old GenerationalId of every
+    ///moved Entity to its new one. Fails without changing either World if other has no
+    ///ComponentManager for one of the Components, or rejects one of them, such as a NameComponent
+    ///whose name is already taken in a unique NameComponentManager.
+    pub fn transfer(&mut self, handle: GenerationalId, other: &mut World) -> Result<(GenerationalId, HashMap<GenerationalId, GenerationalId>), String> {
+        if !self.is_alive(handle) {
+            return Err(format!("Entity ID {} is not active", handle.id));
+        }
+
+        let subtree = self.subtree(handle);
+        let remap = self.move_entities(&subtree, other)?;
+
+        Ok((remap[&handle], remap))
+    }
+
+    ///Moves every Entity of other, along with all of their Components and parent/child links, into
+    ///this World. Resources and Events of other are not moved.
+    ///
+    ///Returns a table mapping the GenerationalId of every Entity in other to its new one. Fails
+    ///without moving anything if this World has no ComponentManager for one of the Components, or
+    ///rejects one of them.
+    pub fn merge(&mut self, mut other: World) -> Result<HashMap<GenerationalId, GenerationalId>, String> {
+        other.flush_reserved();
+
+        let ids: Vec<GenerationalId> = other.iter_entities().map(|e| e.id).collect();
+        other.move_entities(&ids, self)
+    }
+
+    //Returns the Entity followed by all of its descendants, each level in order.
+    fn subtree(&self, handle: GenerationalId) -> Vec<GenerationalId> {
+        let mut subtree = vec![handle];
+        let mut i = 0;
+        while i < subtree.len() {
+            subtree.extend_from_slice(&self.children[subtree[i].id as usize]);
+            i += 1;
+        }
+
+        subtree
+    }
+
+    //Moves the given active Entities and their Components into other, keeping the parent/child
+    //links between them, then deletes them from this World. Whether other has a ComponentManager
+    //for every Component, and whether each would accept all of the copies, is checked before
+    //anything is allocated in other, so a rejected move changes neither World.
+    fn move_entities(&mut self, ids: &[GenerationalId], other: &mut World) -> Result<HashMap<GenerationalId, GenerationalId>, String> {
+        for (t, m) in self.component_managers.iter() {
+            let m = m.read().unwrap();
+            if !other.accepts(*t) {
+                if let Some(c) = ids.iter().find_map(|id| m.fetch_dyn(*id)) {
+                    return Err(format!("No successfully registered ComponentManager for {}", c.type_name()));
+                }
+            }
+        }
+        for id in ids.iter() {
+            for (t, name) in self.archetypes.read().unwrap().types_of(*id) {
+                if !other.accepts(t) {
+                    return Err(format!("No successfully registered ComponentManager for {}", name));
+                }
+            }
+        }
+
+        let mut comps = Vec::with_capacity(ids.len());
+        for id in ids.iter() {
+            comps.push(self.clone_components_of(*id)?.1);
+        }
+
+        let all: Vec<&dyn Component> = comps.iter().flatten().map(|c| &**c).collect();
+        other.check_insert_all(&all)?;
+
+        let new_ids = other.spawn_batch(ids.len());
+        let remap: HashMap<GenerationalId, GenerationalId> = ids.iter().copied().zip(new_ids.iter().copied()).collect();
+
+        //Copies are attached before anything is deleted here, so that a manager rejecting one at
+        //insert despite passing the check only has to undo the new Entities in other.
+        for (new_id, c) in new_ids.iter().zip(comps) {
+            if let Err(e) = other.insert_all_dyn(*new_id, c) {
+                for n in new_ids.iter() {
+                    other.unspawn(*n);
+                }
+                return Err(e);
+            }
+        }
+
+        for id in ids.iter() {
+            other.entities[remap[id].id as usize].enabled = self.entities[id.id as usize].enabled;
+
+            for c in self.children[id.id as usize].iter() {
+                if let Some(new_c) = remap.get(c) {
+                    other.set_parent(*new_c, remap[id])?;
+                }
+            }
+        }
+
+        for id in ids.iter() {
+            self.delete(*id)?;
+        }
+
+        Ok(remap)
+    }
+
+    ///Returns a Commands buffer for recording changes that need a mutable World, such as from
+    ///within System::run.
+    pub fn commands(&self) -> Commands<'_> {
+        Commands::new(self, &self.commands)
+    }
+
+    ///Applies every recorded Command in the order it was recorded, continuing past any that fail.
+    ///Returns the errors of the failed Commands, if there were any.
+    pub fn apply_commands(&mut self) -> Result<(), String> {
+        self.flush_reserved();
+
+        let ops = std::mem::take(&mut self.commands.get_mut().unwrap().ops);
+        let mut errors = Vec::new();
+
+        for op in ops {
+            if let Err(e) = op(self) {
+                errors.push(e);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    //Returns the GenerationalId that the k-th next spawn will receive. Recycled slots are handed
+    //out first, followed by new slots appended after the last Entity.
+    pub(crate) fn peek_free(&self, k: usize) -> GenerationalId {
+        match self.free_queue.peek(k) {
+            Some(id) => id,
+            None => {
+                let i = self.entities.len() + k - self.free_queue.available();
+                GenerationalId::new(i as u32, self.fresh_gen)
+            }
+        }
+    }
+
+    //Makes every GenerationalId reserved through Commands::spawn active, in the order they were
+    //reserved. Must run before anything else touches the free queue.
+    fn flush_reserved(&mut self) {
+        let reserved = std::mem::take(&mut self.commands.get_mut().unwrap().reserved);
+
+        for r in reserved {
+            let id = self.alloc();
+            debug_assert_eq!(id, r);
+        }
+    }
+
+    ///Performs any potentially deferred operations such as Entity creation or deletion and updates all ComponentManagers.
+    ///Returns the errors of any recorded Commands that failed, after every update has still run.
+    pub fn update(&mut self) -> Result<(), String> {
+        let res = self.apply_commands();
+
+        for u in self.event_updaters.values() {
+            u(self);
+        }
+
+        for m in self.component_managers.values_mut() {
+            let _ = m.write().unwrap().general_update();
+        }
+
+        res
+    }
+
+    ///Asks the game to quit running, giving the reason why. Only the first request is kept.
+    pub fn request_quit(&self, reason: &str) {
+        let mut quit = self.quit.write().unwrap();
+        if quit.is_none() {
+            *quit = Some(reason.to_string());
+        }
+    }
+
+    ///Returns whether or not the game should quit running.
+    pub fn should_quit(&self) -> bool {
+        self.quit.read().unwrap().is_some()
+    }
+
+    ///Returns the reason the game should quit running, if it should.
+    pub fn quit_reason(&self) -> Option<String> {
+        self.quit.read().unwrap().clone()
+    }
+
+    ///Forgets any request to quit, so the game can be run again.
+    pub fn clear_quit(&self) {
+        *self.quit.write().unwrap() = None;
+    }
+
+}
+
+//Swaps the buffers of the Events of type E, if they are still registered.
+fn update_events<E: Resource>(w: &World) {
+    if let Some(mut events) = w.resource_mut::<Events<E>>() {
+        events.update();
+    }
+}
+
+//Adds the TypeId of every Component referenced by a QueryElement.
+fn collect_types(qe: &QueryElement, types: &mut Vec<TypeId>) {
+    match qe {
+        QueryElement::Part(p) => types.push(p.comp),
+        QueryElement::Not(q) => collect_types(q, types),
+        QueryElement::Or(l, r) | QueryElement::And(l, r) | QueryElement::Xor(l, r) => {
+            collect_types(l, types);
+            collect_types(r, types);
+        }
+    }
+}
+
+//Returns whether or not an Entity fulfills a QueryElement. A Component without a registered
+//ComponentManager is only present if it is stored in the Archetypes.
+fn matches(qe: &QueryElement, id: GenerationalId, guards: &HashMap<TypeId, std::sync::RwLockReadGuard<Box<dyn GeneralComponentManager>>>, archetypes: &ArchetypeStorage) -> bool {
+    match qe {
+        QueryElement::Part(p) => match guards.get(&p.comp) {
+            Some(m) => m.general_has_component(id),
+            None => archetypes.contains(id, p.comp)
+        },
+        QueryElement::Not(q) => !matches(q, id, guards, archetypes),
+        QueryElement::Or(l, r) => matches(l, id, guards, archetypes) || matches(r, id, guards, archetypes),
+        QueryElement::And(l, r) => matches(l, id, guards, archetypes) && matches(r, id, guards, archetypes),
+        QueryElement::Xor(l, r) => matches(l, id, guards, archetypes) != matches(r, id, guards, archetypes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::vec_storage::*;
+    use crate::component::map_storage::*;
+    use crate::component::test_components::*;
+    use std::sync::Mutex;
+
+    fn unowned() -> GenerationalId {
+        GenerationalId::new(0, 0)
+    }
+
+    //Returns a World with unique names, where every on_add and on_remove of a NameComponent is
+    //logged.
+    fn logged_names() -> (World, Arc<Mutex<Vec<String>>>) {
+        let mut w = World::new();
+        w.register_manager(NameComponentManager::new_unique());
+        w.register_manager(VecStorage::<Position>::new());
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (add, rm) = (log.clone(), log.clone());
+        w.on_add::<NameComponent, _>(move |o, _| add.lock().unwrap().push(format!("add {:?}", (o.id, o.gen)))).unwrap();
+        w.on_remove::<NameComponent, _>(move |o, _| rm.lock().unwrap().push(format!("rm {:?}", (o.id, o.gen)))).unwrap();
+
+        (w, log)
+    }
+
+    #[test]
+    fn spawn_with_rejected_name_allocates_nothing() {
+        let (mut w, log) = logged_names();
+        let first = w.spawn_with((NameComponent::new("X".to_string()),)).unwrap();
+        w.update().unwrap();
+        log.lock().unwrap().clear();
+
+        let res = w.spawn_with((Position::new(unowned(), 1), NameComponent::new("X".to_string())));
+        w.update().unwrap();
+
+        assert!(res.is_err());
+        assert!(log.lock().unwrap().is_empty());
+        assert_eq!(w.removed::<NameComponent>().unwrap().count(), 0);
+        assert_eq!(w.removed::<Position>().unwrap().count(), 0);
+        assert_eq!(w.entity_count(), 1);
+        assert_eq!(w.find_all_by_name("X"), vec![first]);
+    }
+
+    #[test]
+    fn bundle_with_repeated_unique_name_is_rejected() {
+        let (mut w, log) = logged_names();
+        let e = w.spawn();
+
+        let res = w.attach_bundle(e, (NameComponent::new("X".to_string()), Position::new(unowned(), 1)));
+        assert!(res.is_ok());
+
+        let dup = w.duplicate(e);
+        assert!(dup.is_err());
+        assert_eq!(w.entity_count(), 1);
+        assert_eq!(log.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn set_parent_rejects_cycles() {
+        let mut w = World::new();
+        let ids = w.spawn_batch(3);
+        w.set_parent(ids[1], ids[0]).unwrap();
+        w.set_parent(ids[2], ids[1]).unwrap();
+
+        assert!(w.set_parent(ids[0], ids[0]).is_err());
+        assert!(w.set_parent(ids[0], ids[2]).is_err());
+        assert!(w.set_parent(ids[1], ids[2]).is_err());
+        assert_eq!(w.ancestors(ids[2]), vec![ids[1], ids[0]]);
+        assert!(w.ancestors(ids[0]).is_empty());
+
+        //Moving a subtree under another branch replaces the old link on both ends.
+        let other = w.spawn();
+        w.set_parent(ids[1], other).unwrap();
+        assert!(w.children(ids[0]).is_empty());
+        assert_eq!(w.ancestors(ids[2]), vec![ids[1], other]);
+    }
+
+    #[test]
+    fn delete_recursive_removes_every_descendant() {
+        let mut w = World::new();
+        let ids = w.spawn_batch(5);
+        w.set_parent(ids[1], ids[0]).unwrap();
+        w.set_parent(ids[2], ids[1]).unwrap();
+        w.set_parent(ids[3], ids[0]).unwrap();
+        w.set_parent(ids[0], ids[4]).unwrap();
+
+        w.delete_recursive(ids[0]).unwrap();
+
+        assert_eq!(w.entity_count(), 1);
+        assert!(ids[..4].iter().all(|id| !w.is_alive(*id)));
+        assert!(w.children(ids[4]).is_empty());
+        assert!(w.delete_recursive(ids[0]).is_err());
+    }
+
+    #[test]
+    fn reused_slots_start_without_links() {
+        let mut w = World::new();
+        let ids = w.spawn_batch(3);
+        w.set_parent(ids[1], ids[0]).unwrap();
+        w.set_parent(ids[2], ids[1]).unwrap();
+
+        w.delete(ids[1]).unwrap();
+        assert_eq!(w.parent(ids[2]), None);
+        assert!(w.children(ids[0]).is_empty());
+
+        let reused = w.spawn();
+        assert_eq!(reused.id, ids[1].id);
+        assert_eq!(w.parent(reused), None);
+        assert!(w.children(reused).is_empty());
+        assert_eq!(w.parent(ids[1]), None);
+        assert!(w.children(ids[1]).is_empty());
+    }
+
+    fn live_ids(w: &World) -> Vec<GenerationalId> {
+        w.iter_entities().map(|e| e.id).collect()
+    }
+
+    #[test]
+    fn spawn_batch_fills_recycled_slots_before_fresh_ones() {
+        let mut w = World::new();
+        let ids = w.spawn_batch(4);
+        w.delete(ids[1]).unwrap();
+        w.delete(ids[3]).unwrap();
+
+        let batch = w.spawn_batch(4);
+
+        assert_eq!(batch, vec![id(3, 2), id(1, 2), id(4, 1), id(5, 1)]);
+        assert!(batch.iter().all(|id| w.is_alive(*id)));
+        assert_eq!(w.entity_count(), 6);
+        assert_eq!(live_ids(&w), vec![ids[0], id(1, 2), ids[2], id(3, 2), id(4, 1), id(5, 1)]);
+    }
+
+    #[test]
+    fn spawn_batch_leaves_fifo_slots_held_back() {
+        let mut w = World::new();
+        w.set_slot_reuse(SlotReuse::Fifo { min_free: 1 });
+        let ids = w.spawn_batch(4);
+        w.delete(ids[1]).unwrap();
+        w.delete(ids[3]).unwrap();
+
+        //Only slot 1 may be reused while slot 3 keeps the free queue at min_free.
+        let batch = w.spawn_batch(3);
+
+        assert_eq!(batch, vec![id(1, 2), id(4, 1), id(5, 1)]);
+        assert_eq!(w.entity_count(), 5);
+        assert!(!w.is_alive(ids[3]) && !w.is_alive(id(3, 2)));
+        assert_eq!(live_ids(&w), vec![ids[0], id(1, 2), ids[2], id(4, 1), id(5, 1)]);
+    }
+
+    #[test]
+    fn iter_entities_skips_dead_and_reserved_slots() {
+        let mut w = World::new();
+        let ids = w.spawn_batch(3);
+        w.delete(ids[0]).unwrap();
+
+        let reserved: Vec<GenerationalId> = {
+            let mut cmd = w.commands();
+            vec![cmd.spawn(), cmd.spawn()]
+        };
+        assert_eq!(reserved, vec![id(0, 2), id(3, 1)]);
+
+        assert_eq!(live_ids(&w), vec![ids[1], ids[2]]);
+        assert_eq!(w.entity_count(), 2);
+
+        w.apply_commands().unwrap();
+        assert_eq!(live_ids(&w), vec![reserved[0], ids[1], ids[2], reserved[1]]);
+        assert_eq!(w.entity_count(), 4);
+    }
+
+    #[test]
+    fn unspawn_moves_slot_to_next_generation() {
+        let mut w = World::new();
+        let id = w.spawn();
+
+        w.unspawn(id);
+        let next = w.spawn();
+
+        assert_eq!(next.id, id.id);
+        assert_eq!(next.gen, id.gen + 1);
+        assert!(!w.is_alive(id));
+    }
+
+    //Returns a World with the given GenerationPolicy whose first of two Entities is on its last
+    //generation.
+    fn exhausted(policy: GenerationPolicy) -> (World, GenerationalId) {
+        let mut w = World::new();
+        w.set_generation_policy(policy);
+        w.spawn_batch(2);
+        w.entities[0].id.gen = u32::MAX;
+        (w, GenerationalId::new(0, u32::MAX))
+    }
+
+    #[test]
+    fn retire_policy_never_reuses_an_exhausted_slot() {
+        let (mut w, last) = exhausted(GenerationPolicy::Retire);
+        assert_eq!(w.retired_count(), 0);
+
+        w.delete(last).unwrap();
+
+        assert_eq!(w.retired_count(), 1);
+        assert_eq!(w.spawn().id, 2);
+        assert_eq!(w.entity_count(), 2);
+    }
+
+    #[test]
+    fn wrap_policy_restarts_at_generation_one() {
+        let (mut w, last) = exhausted(GenerationPolicy::Wrap);
+
+        w.delete(last).unwrap();
+
+        assert_eq!(w.retired_count(), 0);
+        assert_eq!(w.spawn(), GenerationalId::new(0, 1));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "exhausted its generations")]
+    fn panic_policy_panics_in_debug_builds() {
+        let (mut w, last) = exhausted(GenerationPolicy::Panic);
+
+        let _ = w.delete(last);
+    }
+
+    //Returns a World with a Position on each of n Entities, holding its index.
+    fn positions(n: usize) -> (World, Vec<GenerationalId>) {
+        let mut w = World::new();
+        w.register_manager(VecStorage::<Position>::new());
+
+        let ids = w.spawn_batch(n);
+        for (i, id) in ids.iter().enumerate() {
+            w.attach_component(*id, Position::new(*id, i as i32)).unwrap();
+        }
+
+        (w, ids)
+    }
+
+    fn x_of(w: &World, id: GenerationalId) -> Option<i32> {
+        let m = w.manager::<Position>().unwrap();
+        let x = downcast_read_lock::<Position>(&m).fetch(id).map(|p| p.x);
+        x
+    }
+
+    #[test]
+    fn duplicate_copies_components_parent_and_enabled_state() {
+        let (mut w, ids) = positions(2);
+        w.register_manager(NameComponentManager::new());
+        w.attach_component(ids[1], NameComponent::new("N".to_string())).unwrap();
+        w.set_parent(ids[1], ids[0]).unwrap();
+        w.set_enabled(ids[1], false).unwrap();
+
+        let dup = w.duplicate(ids[1]).unwrap();
+
+        assert_ne!(dup, ids[1]);
+        assert_eq!(x_of(&w, dup), Some(1));
+        {
+            let m = w.manager::<Position>().unwrap();
+            assert_eq!(downcast_read_lock::<Position>(&m).fetch(dup).unwrap().get_owner(), dup);
+        }
+        assert_eq!(w.find_all_by_name("N"), vec![ids[1], dup]);
+        assert_eq!(w.parent(dup), Some(ids[0]));
+        assert_eq!(w.children(ids[0]), &[ids[1], dup]);
+        assert!(w.is_alive(dup) && !w.is_enabled(dup));
+        assert_eq!(x_of(&w, ids[1]), Some(1));
+    }
+
+    #[test]
+    fn duplicate_into_copies_to_another_world() {
+        let (mut src, ids) = positions(2);
+        src.set_parent(ids[1], ids[0]).unwrap();
+        src.set_enabled(ids[1], false).unwrap();
+        let mut dst = World::new();
+        dst.register_manager(VecStorage::<Position>::new());
+        dst.spawn();
+
+        let dup = src.duplicate_into(ids[1], &mut dst).unwrap();
+
+        assert_eq!(dup.id, 1);
+        assert_eq!(x_of(&dst, dup), Some(1));
+        {
+            let m = dst.manager::<Position>().unwrap();
+            assert_eq!(downcast_read_lock::<Position>(&m).fetch(dup).unwrap().get_owner(), dup);
+        }
+        assert!(!dst.is_enabled(dup));
+        assert_eq!(dst.parent(dup), None);
+        assert_eq!(src.entity_count(), 2);
+        assert_eq!(x_of(&src, ids[1]), Some(1));
+    }
+
+    #[test]
+    fn attach_dyn_routes_by_concrete_type() {
+        let (mut w, ids) = positions(1);
+        w.register_manager(MapStorage::<Velocity>::new());
+        let id = w.spawn();
+
+        w.attach_dyn(id, Box::new(Velocity::new(unowned(), 3))).unwrap();
+        w.attach_dyn(id, Box::new(Position::new(unowned(), 4))).unwrap();
+
+        assert_eq!(x_of(&w, id), Some(4));
+        let m = w.manager::<Velocity>().unwrap();
+        let v = downcast_read_lock::<Velocity>(&m).fetch(id).unwrap();
+        assert_eq!((v.dx, v.get_owner()), (3, id));
+        drop(m);
+
+        assert!(w.attach_dyn(id, Box::new(Tagged::<Frozen>::new())).is_err());
+        w.register_tag::<Frozen>();
+        w.attach_dyn(id, Box::new(Tagged::<Frozen>::new())).unwrap();
+        assert_eq!(w.tagged::<Frozen>(), vec![id]);
+
+        assert!(w.attach_dyn(ids[0], Box::new(Position::new(unowned(), 1))).is_err());
+        assert_eq!(x_of(&w, ids[0]), Some(0));
+
+        assert!(w.attach_dyn(id, Box::new(NameComponent::new("N".to_string()))).is_err());
+        assert!(w.attach_dyn(GenerationalId::new(9, 1), Box::new(Position::new(unowned(), 0))).is_err());
+    }
+
+    #[test]
+    fn compact_moves_components_and_hierarchy() {
+        let (mut w, ids) = positions(5);
+        w.set_parent(ids[4], ids[3]).unwrap();
+        w.set_enabled(ids[4], false).unwrap();
+        w.delete(ids[0]).unwrap();
+        w.delete(ids[2]).unwrap();
+
+        let remap = w.compact().unwrap();
+
+        assert_eq!(remap.len(), 3);
+        let (a, b, c) = (remap[&ids[1]], remap[&ids[3]], remap[&ids[4]]);
+        assert_eq!((a.id, b.id, c.id), (0, 1, 2));
+        assert_eq!((x_of(&w, a), x_of(&w, b), x_of(&w, c)), (Some(1), Some(3), Some(4)));
+        assert_eq!(w.parent(c), Some(b));
+        assert_eq!(w.children(b), &[c]);
+        assert!(!w.is_enabled(c));
+        assert!(!w.is_alive(ids[1]) && !w.is_alive(ids[3]));
+    }
+
+    #[test]
+    fn compact_never_hands_out_an_old_id() {
+        let (mut w, ids) = positions(4);
+        w.delete(ids[2]).unwrap();
+        w.delete(ids[1]).unwrap();
+
+        let remap = w.compact().unwrap();
+        let moved = remap[&ids[3]];
+        assert_eq!(moved.id, 1);
+        assert!(moved.gen > ids[1].gen);
+
+        let fresh = w.spawn_batch(3);
+        for id in fresh.iter().chain(std::iter::once(&moved)) {
+            assert!(!ids.contains(id));
+        }
+        assert_eq!(fresh.iter().filter(|f| f.id == 2).count(), 1);
+        assert!(fresh.iter().all(|f| f.gen > 1));
+    }
+
+    #[test]
+    fn compact_keeps_retired_slots_retired() {
+        let (mut w, ids) = positions(3);
+        w.entities[0].id.gen = u32::MAX;
+        let exhausted = GenerationalId::new(0, u32::MAX);
+        w.delete(exhausted).unwrap();
+        assert_eq!(w.retired_count(), 1);
+
+        let remap = w.compact().unwrap();
+
+        assert_eq!(remap[&ids[1]], ids[1]);
+        assert_eq!(remap[&ids[2]], ids[2]);
+        assert_eq!(w.retired_count(), 1);
+        assert_ne!(w.spawn().id, 0);
+    }
+
+    #[test]
+    fn compact_keeps_removed_until_update() {
+        let (mut w, ids) = positions(3);
+        w.update().unwrap();
+        w.delete(ids[0]).unwrap();
+        w.update().unwrap();
+
+        w.compact().unwrap();
+        assert_eq!(w.removed::<Position>().unwrap().collect::<Vec<_>>(), vec![ids[0]]);
+
+        w.update().unwrap();
+        assert_eq!(w.removed::<Position>().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn compact_returns_command_errors() {
+        let (mut w, ids) = positions(3);
+        w.delete(ids[0]).unwrap();
+        w.commands().delete(GenerationalId::new(40, 1));
+
+        assert!(w.compact().is_err());
+        assert!(w.is_alive(ids[1]));
+        assert_eq!(x_of(&w, ids[2]), Some(2));
+    }
+
+    #[test]
+    fn rejected_transfer_changes_neither_world() {
+        let (mut dst, log) = logged_names();
+        dst.spawn_with((NameComponent::new("X".to_string()),)).unwrap();
+        dst.update().unwrap();
+        log.lock().unwrap().clear();
+        let slots = dst.entities.len();
+
+        let mut src = World::new();
+        src.register_manager(NameComponentManager::new());
+        src.register_manager(VecStorage::<Position>::new());
+        let root = src.spawn_with((Position::new(unowned(), 1),)).unwrap();
+        let child = src.spawn_with((NameComponent::new("X".to_string()),)).unwrap();
+        src.set_parent(child, root).unwrap();
+
+        assert!(src.transfer(root, &mut dst).is_err());
+        dst.update().unwrap();
+        src.update().unwrap();
+
+        assert_eq!(dst.entity_count(), 1);
+        assert_eq!(dst.entities.len(), slots);
+        assert!(log.lock().unwrap().is_empty());
+        assert_eq!(dst.removed::<NameComponent>().unwrap().count(), 0);
+        assert_eq!(dst.removed::<Position>().unwrap().count(), 0);
+        assert!(src.is_alive(root) && src.is_alive(child));
+        assert_eq!(src.children(root), &[child]);
+    }
+
+    #[test]
+    fn merge_rejects_names_repeated_among_the_moved_entities() {
+        let (mut dst, log) = logged_names();
+
+        let mut src = World::new();
+        src.register_manager(NameComponentManager::new());
+        for _ in 0..2 {
+            src.spawn_with((NameComponent::new("Y".to_string()),)).unwrap();
+        }
+
+        assert!(dst.merge(src).is_err());
+        assert_eq!(dst.entity_count(), 0);
+        assert!(dst.entities.is_empty());
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn transfer_keeps_tree_and_enabled_state() {
+        let (mut src, ids) = positions(3);
+        src.set_parent(ids[1], ids[0]).unwrap();
+        src.set_parent(ids[2], ids[1]).unwrap();
+        src.set_enabled(ids[2], false).unwrap();
+
+        let mut dst = World::new();
+        dst.register_manager(VecStorage::<Position>::new());
+        dst.spawn();
+
+        let (root, remap) = src.transfer(ids[0], &mut dst).unwrap();
+
+        assert_eq!(remap.len(), 3);
+        assert_eq!(dst.children(root), &[remap[&ids[1]]]);
+        assert_eq!(dst.parent(remap[&ids[2]]), Some(remap[&ids[1]]));
+        assert!(!dst.is_enabled(remap[&ids[2]]));
+        assert_eq!(x_of(&dst, remap[&ids[2]]), Some(2));
+        assert_eq!(src.entity_count(), 0);
+    }
+
+    #[test]
+    fn hooks_run_for_delete_detach_and_update() {
+        let (mut w, log) = logged_names();
+        let ids: Vec<GenerationalId> = ["A", "B", "C"].iter()
+            .map(|n| w.spawn_with((NameComponent::new(n.to_string()),)).unwrap())
+            .collect();
+        log.lock().unwrap().clear();
+
+        w.delete(ids[0]).unwrap();
+        w.detach_component::<NameComponent>(ids[1]).unwrap();
+        assert_eq!(log.lock().unwrap().len(), 1);
+
+        w.update().unwrap();
+        assert_eq!(*log.lock().unwrap(), vec!["rm (0, 1)", "rm (1, 1)"]);
+        assert_eq!(w.removed::<NameComponent>().unwrap().collect::<Vec<_>>(), vec![ids[0], ids[1]]);
+        assert_eq!(w.removed::<Position>().unwrap().count(), 0);
+
+        w.update().unwrap();
+        assert_eq!(w.removed::<NameComponent>().unwrap().count(), 0);
+        assert_eq!(log.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn detached_component_is_gone_before_update() {
+        let (w, ids) = positions(2);
+
+        w.detach_component::<Position>(ids[0]).unwrap();
+
+        let mut seen = Vec::new();
+        w.for_each::<Position, _>(|p| seen.push(p.x));
+        w.for_each_mut::<Position, _>(|p| seen.push(p.x));
+        assert_eq!(seen, vec![1, 1]);
+        assert_eq!(w.query(&Query::new(QueryElement::has::<Position>())), vec![ids[1]]);
+        assert_eq!(x_of(&w, ids[0]), None);
+
+        w.attach_component(ids[0], Position::new(unowned(), 5)).unwrap();
+        assert_eq!(x_of(&w, ids[0]), Some(5));
+    }
+
+    #[test]
+    fn take_component_clears_owner_in_either_storage() {
+        let (w, ids) = positions(2);
+
+        let p = w.take_component::<Position>(ids[1]).unwrap();
+        assert_eq!((p.x, p.get_owner()), (1, unowned()));
+        assert_eq!(x_of(&w, ids[1]), None);
+        assert!(w.take_component::<Position>(ids[1]).is_err());
+        assert!(w.take_component::<Velocity>(ids[0]).is_err());
+
+        w.attach_component(ids[1], p).unwrap();
+        assert_eq!(x_of(&w, ids[1]), Some(1));
+
+        let mut a = World::new();
+        a.set_storage_mode(StorageMode::Archetypes);
+        let id = a.spawn_with((Position::new(unowned(), 1), Velocity::new(unowned(), 2))).unwrap();
+
+        let p = a.take_component::<Position>(id).unwrap();
+        assert_eq!((p.x, p.get_owner()), (1, unowned()));
+        assert!(a.archetypes().get::<Position>(id).is_none());
+        assert_eq!(a.archetypes().get::<Velocity>(id).unwrap().dx, 2);
+        assert!(a.take_component::<Position>(id).is_err());
+    }
+
+    #[test]
+    fn for_each_skips_components_without_a_live_owner() {
+        let (mut w, ids) = positions(2);
+        w.set_enabled(ids[1], false).unwrap();
+        for owner in [GenerationalId::new(50, 1), unowned(), GenerationalId::new(0, 7)] {
+            let mut m = w.manager_mut::<Position>().unwrap();
+            downcast_write_lock::<Position>(&mut m).insert(owner, Position::new(owner, 9)).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        w.for_each::<Position, _>(|p| seen.push(p.x));
+        w.for_each_mut::<Position, _>(|p| seen.push(p.x));
+        assert_eq!(seen, vec![0, 0]);
+
+        let mut all = 0;
+        w.for_each_including_disabled::<Position, _>(|_| all += 1);
+        assert_eq!(all, 5);
+    }
+
+    #[test]
+    fn for_each_mut_marks_changed_with_or_without_a_slice() {
+        let mut vec = World::new();
+        vec.register_manager(VecStorage::<Position>::new());
+        let mut map = World::new();
+        map.register_manager(MapStorage::<Position>::new());
+
+        for w in [&mut vec, &mut map] {
+            for i in 0..3 {
+                let id = w.spawn();
+                w.attach_component(id, Position::new(id, i)).unwrap();
+            }
+            let since = w.increment_change_tick();
+            w.increment_change_tick();
+
+            w.for_each_mut::<Position, _>(|p| p.x *= 10);
+            let mut xs = Vec::new();
+            w.for_each::<Position, _>(|p| xs.push(p.x));
+            xs.sort_unstable();
+
+            assert_eq!(xs, vec![0, 10, 20]);
+            assert_eq!(w.changed_since::<Position>(since).unwrap().len(), 3);
+        }
+    }
+
+    #[test]
+    fn for_each_mut_only_marks_visited_components() {
+        let (mut w, ids) = positions(3);
+        w.set_enabled(ids[1], false).unwrap();
+        let since = w.increment_change_tick();
+        w.increment_change_tick();
+
+        w.for_each_mut::<Position, _>(|p| p.x += 1);
+        assert_eq!(w.changed_since::<Position>(since).unwrap(), vec![ids[0], ids[2]]);
+
+        w.for_each_mut_including_disabled::<Position, _>(|_| ());
+        assert_eq!(w.changed_since::<Position>(since).unwrap(), ids);
+    }
+
+    struct Frozen;
+    impl Tag for Frozen {}
+    impl QueryTarget for Frozen {}
+
+    #[test]
+    fn queries_match_tags_and_components() {
+        let (mut w, ids) = positions(3);
+        w.register_tag::<Frozen>();
+        let bare = w.spawn();
+        w.attach_tag::<Frozen>(ids[1]).unwrap();
+        w.attach_tag::<Frozen>(bare).unwrap();
+
+        let mut q = Query::new(QueryElement::has::<Frozen>());
+        assert_eq!(w.query(&q), vec![ids[1], bare]);
+
+        q.and(QueryElement::has::<Position>());
+        assert_eq!(w.query(&q), vec![ids[1]]);
+
+        let q = Query::new(QueryElement::and(QueryElement::read::<Position>(), QueryElement::not(QueryElement::has::<Frozen>())));
+        assert_eq!(w.query(&q), vec![ids[0], ids[2]]);
+    }
+
+    #[test]
+    fn detached_tag_can_be_attached_again_before_update() {
+        let (mut w, ids) = positions(2);
+        w.register_tag::<Frozen>();
+        w.attach_tag::<Frozen>(ids[1]).unwrap();
+
+        w.detach_tag::<Frozen>(ids[1]).unwrap();
+        assert!(!w.has_tag::<Frozen>(ids[1]));
+        w.attach_tag::<Frozen>(ids[1]).unwrap();
+        w.update().unwrap();
+        assert!(w.has_tag::<Frozen>(ids[1]));
+
+        let (_, comps) = w.clone_components_of(ids[1]).unwrap();
+        assert!(comps.iter().all(|c| c.get_owner() == ids[1]));
+
+        let copy = w.duplicate(ids[1]).unwrap();
+        assert_eq!(w.tagged::<Frozen>(), vec![ids[1], copy]);
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+
+    #[test]
+    fn resources_are_inserted_replaced_and_removed() {
+        let mut w = World::new();
+        assert!(w.resource::<Score>().is_none());
+
+        assert_eq!(w.insert_resource(Score(1)), None);
+        assert_eq!(w.insert_resource(Score(2)), Some(Score(1)));
+        assert_eq!(*w.resource::<Score>().unwrap(), Score(2));
+
+        w.resource_mut::<Score>().unwrap().0 += 5;
+        assert_eq!(w.resource::<Score>().unwrap().0, 7);
+
+        assert_eq!(w.remove_resource::<Score>(), Some(Score(7)));
+        assert!(w.resource_mut::<Score>().is_none());
+        assert_eq!(w.remove_resource::<Score>(), None);
+    }
+
+    #[test]
+    fn archetype_bundle_is_stored_in_one_move() {
+        let mut w = World::new();
+        w.set_storage_mode(StorageMode::Archetypes);
+        w.register_manager(NameComponentManager::new());
+
+        let id = w.spawn_with((Position::new(unowned(), 1), NameComponent::new("A".to_string()), Velocity::new(unowned(), 2))).unwrap();
+
+        let a = w.archetypes();
+        assert_eq!(a.archetypes().len(), 1);
+        assert_eq!(a.get::<Position>(id).unwrap().get_owner(), id);
+        assert_eq!(a.get::<Velocity>(id).unwrap().dx, 2);
+        drop(a);
+        assert_eq!(w.find_by_name("A"), Some(id));
+    }
+
+    #[test]
+    fn for_each2_skips_disabled_and_writes_both_columns() {
+        let mut w = World::new();
+        w.set_storage_mode(StorageMode::Archetypes);
+        let ids: Vec<GenerationalId> = (0..3)
+            .map(|i| w.spawn_with((Position::new(unowned(), i), Velocity::new(unowned(), 10))).unwrap())
+            .collect();
+        let lone = w.spawn_with((Position::new(unowned(), 7),)).unwrap();
+        w.set_enabled(ids[1], false).unwrap();
+
+        w.for_each2_mut::<Position, Velocity, _>(|p, v| {
+            p.x += v.dx;
+            v.dx *= 2;
+        });
+
+        let mut seen = Vec::new();
+        w.for_each2::<Position, Velocity, _>(|p, v| seen.push((p.x, v.dx)));
+        assert_eq!(seen, vec![(10, 20), (12, 20)]);
+
+        let a = w.archetypes();
+        assert_eq!((a.get::<Position>(ids[1]).unwrap().x, a.get::<Velocity>(ids[1]).unwrap().dx), (1, 10));
+        assert_eq!(a.get::<Position>(lone).unwrap().x, 7);
+    }
+
+    #[test]
+    fn changes_to_archetype_types_are_errors() {
+        let mut w = World::new();
+        w.set_storage_mode(StorageMode::Archetypes);
+        let id = w.spawn_with((Position::new(unowned(), 1),)).unwrap();
+        w.detach_component::<Position>(id).unwrap();
+        w.update().unwrap();
+
+        assert!(w.added::<Position>().is_err());
+        assert!(w.changed::<Position>().is_err());
+        assert!(w.removed::<Position>().is_err());
+        assert!(w.on_add::<Position, _>(|_, _| ()).is_err());
+        assert!(w.removed::<Velocity>().is_err());
+    }
 }